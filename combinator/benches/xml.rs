@@ -0,0 +1,58 @@
+//! XML解析器的基准测试：`cargo bench -p combinator --bench xml --features bench`
+//! 三种生成的文档分别考察少量元素的固定开销、大量属性与字符串的吞吐量以及深层递归
+
+use std::hint::black_box;
+
+use combinator::bench;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+const SMALL: &str = r#"
+    <top label="Top">
+        <semi-bottom label="Bottom"/>
+        <middle>
+            <bottom label="Another bottom"/>
+        </middle>
+    </top>"#;
+
+/// 一个根元素下有`width`个带有两个属性的子元素
+fn wide(width: usize) -> String {
+    let mut doc = String::from("<root>\n");
+    for i in 0..width {
+        doc.push_str(&format!(
+            "    <item id=\"item-{}\" description=\"the quick brown fox jumps over the lazy dog\"/>\n",
+            i
+        ));
+    }
+    doc.push_str("</root>");
+    doc
+}
+
+/// 嵌套`depth`层的元素，每一层都有一个属性，`depth`不能超过`bench::MAX_DEPTH`
+fn deep(depth: usize) -> String {
+    let mut doc = String::new();
+    for i in 0..depth {
+        doc.push_str(&format!("<level depth=\"{}\">", i));
+    }
+    doc.push_str("<leaf/>");
+    doc.push_str(&"</level>".repeat(depth));
+    doc
+}
+
+fn parse(c: &mut Criterion) {
+    let docs = [("small", SMALL.to_owned()), ("wide", wide(2_000)), ("deep", deep(100))];
+    let mut group = c.benchmark_group("xml");
+    for (name, doc) in &docs {
+        assert!(bench::element(doc).is_some() && bench::limited(doc).is_some());
+        group.throughput(Throughput::Bytes(doc.len() as u64));
+        group.bench_with_input(BenchmarkId::new("element", name), doc, |b, doc| {
+            b.iter(|| bench::element(black_box(doc)))
+        });
+        group.bench_with_input(BenchmarkId::new("parse_xml", name), doc, |b, doc| {
+            b.iter(|| bench::limited(black_box(doc)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,235 @@
+//! 直接从`AsyncRead`解析
+//! 读到的数据交给`streaming::Driver`，每解析完一个顶层的文档就作为`Stream`的一项产出，
+//! 不需要先把整个输入读进内存
+//!
+//! 一个文档解析完成后，缓冲区中剩下的数据会先被解析，之后才继续读取；
+//! 输入结束时剩下的内容只能是空白，否则产出`UnexpectedEof`
+//!
+//! 输入可能来自不可信的连接，嵌套深度等由`Limits`限制，还没有解析的数据由`max_buffered`限制，
+//! 超出时产出错误，而不是耗尽栈空间或者内存
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{
+    Element,
+    streaming::{Driver, Feed, FeedError},
+    xml::Limits,
+};
+
+/// 每次读取的字节数
+const CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    Io(io::Error),
+    Feed(FeedError),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        ReadError::Io(err)
+    }
+}
+
+impl From<FeedError> for ReadError {
+    fn from(err: FeedError) -> Self {
+        ReadError::Feed(err)
+    }
+}
+
+/// 从`reader`中依次解析出文档的异步流，出错之后流就结束了
+pub(crate) struct AsyncDriver<R> {
+    reader: R,
+    /// 输入结束或者出错之后为`None`
+    driver: Option<Driver>,
+    chunk: Box<[u8]>,
+    /// 上一次解析出文档之后，缓冲区中可能已经有下一个文档
+    pending: bool,
+}
+
+impl<R> AsyncDriver<R>
+where
+    R: AsyncRead + Unpin,
+{
+    pub(crate) fn new(reader: R, limits: Limits) -> Self {
+        Self {
+            reader,
+            driver: Some(Driver::new(limits)),
+            chunk: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            pending: false,
+        }
+    }
+
+    /// 见`Driver::max_buffered`
+    pub(crate) fn max_buffered(mut self, max_buffered: usize) -> Self {
+        self.driver = self.driver.map(|driver| driver.max_buffered(max_buffered));
+        self
+    }
+}
+
+impl<R> Stream for AsyncDriver<R>
+where
+    R: AsyncRead + Unpin,
+{
+    type Item = Result<Element, ReadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let Some(driver) = this.driver.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            let fed = if this.pending {
+                this.pending = false;
+                driver.feed(&[])
+            } else {
+                let mut buf = ReadBuf::new(&mut this.chunk);
+                if let Err(err) = ready!(Pin::new(&mut this.reader).poll_read(cx, &mut buf)) {
+                    this.driver = None;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                if buf.filled().is_empty() {
+                    let finished = this.driver.take().unwrap().finish();
+                    return Poll::Ready(finished.err().map(|err| Err(err.into())));
+                }
+                driver.feed(buf.filled())
+            };
+
+            match fed {
+                Ok(Feed::Done(output)) => {
+                    this.pending = true;
+                    return Poll::Ready(Some(Ok(output)));
+                }
+                Ok(Feed::NeedMore(_)) => {}
+                Err(err) => {
+                    this.driver = None;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use tokio::io::{AsyncWriteExt, duplex};
+
+    use super::*;
+    use crate::{
+        Parser,
+        xml::{XmlError, XmlErrorKind},
+    };
+
+    const DOCS: &str = r#"
+        <top label="Top">
+            <semi-bottom label="Bottom"/>
+        </top>
+        <子元素 label="中文"/>
+        <middle>
+            <bottom/>
+        </middle>
+    "#;
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    /// 在后台把`input`分成`size`字节的小块写入管道，写完后关闭写端
+    /// 出错之后读取端不再读取，剩下的数据不必再写
+    fn reader(input: impl AsRef<[u8]> + Send + 'static, size: usize) -> impl AsyncRead + Unpin {
+        let (mut writer, reader) = duplex(size);
+        tokio::spawn(async move {
+            for chunk in input.as_ref().chunks(size) {
+                if writer.write_all(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        reader
+    }
+
+    async fn collect<R: AsyncRead + Unpin>(reader: R) -> Vec<Result<Element, ReadError>> {
+        collect_from(AsyncDriver::new(reader, Limits::default())).await
+    }
+
+    async fn collect_from<R: AsyncRead + Unpin>(
+        mut stream: AsyncDriver<R>,
+    ) -> Vec<Result<Element, ReadError>> {
+        let mut items = vec![];
+        while let Some(item) = next(&mut stream).await {
+            items.push(item);
+        }
+        items
+    }
+
+    #[tokio::test]
+    async fn read_documents() {
+        let expected = crate::zero_or_more(crate::element()).parse(DOCS).unwrap().1;
+        assert_eq!(3, expected.len());
+        // 较小的块会在多字节字符的中间断开
+        for size in [1, 3, 7, 64] {
+            let parsed = collect(reader(DOCS.as_bytes(), size)).await;
+            assert_eq!(expected, parsed.into_iter().map(Result::unwrap).collect::<Vec<_>>());
+        }
+    }
+
+    /// 流可以交给多线程的运行时，在其他线程上继续读取
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn send_across_threads() {
+        let expected = crate::zero_or_more(crate::element()).parse(DOCS).unwrap().1;
+        let parsed = tokio::spawn(async { collect(reader(DOCS.as_bytes(), 7)).await });
+        let parsed = parsed.await.unwrap();
+        assert_eq!(expected, parsed.into_iter().map(Result::unwrap).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn read_errors() {
+        let parsed = collect(reader(b"<a/><b></c>", 2)).await;
+        assert_eq!(2, parsed.len());
+        assert_eq!(Element::new("a"), *parsed[0].as_ref().unwrap());
+        assert!(matches!(
+            parsed[1],
+            Err(ReadError::Feed(FeedError::Xml(XmlError {
+                kind: XmlErrorKind::Syntax,
+                offset: 7
+            })))
+        ));
+
+        let parsed = collect(reader(b"<a/>\n<b>", 2)).await;
+        assert_eq!(2, parsed.len());
+        assert!(matches!(parsed[1], Err(ReadError::Feed(FeedError::UnexpectedEof))));
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let depth = 100_000;
+        let doc = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        let stream = AsyncDriver::new(reader(doc, CHUNK_SIZE), Limits::default().max_depth(8));
+        let parsed = collect_from(stream).await;
+        assert_eq!(1, parsed.len());
+        assert!(matches!(
+            parsed[0],
+            Err(ReadError::Feed(FeedError::Xml(XmlError {
+                kind: XmlErrorKind::TooDeep,
+                offset: 24
+            })))
+        ));
+
+        // 一直没有结束的属性值在超出缓冲区的上限时出错，而不是一直读下去
+        let doc = format!("<a label=\"{}", "x".repeat(1024 * 1024));
+        let stream = AsyncDriver::new(reader(doc, CHUNK_SIZE), Limits::default()).max_buffered(64);
+        let parsed = collect_from(stream).await;
+        assert_eq!(1, parsed.len());
+        assert!(matches!(parsed[0], Err(ReadError::Feed(FeedError::BufferFull { offset: 0 }))));
+    }
+}
@@ -0,0 +1,25 @@
+//! 供`benches/xml.rs`使用的入口
+//! 基准测试是独立的crate，只能访问公开的项，这里把内部的解析器包装成只返回元素数量的函数
+//! 只在启用`bench` feature时编译，不会成为正常构建的公开接口
+
+use crate::{
+    Element, Parser,
+    xml::{Limits, parse_xml},
+};
+
+fn count(el: &Element) -> usize {
+    1 + el.children.iter().map(count).sum::<usize>()
+}
+
+/// 嵌套深度的上限，`benches/xml.rs`中最深的文档不能超过它
+pub const MAX_DEPTH: usize = 128;
+
+/// 使用`element`解析，它带有默认的限制，返回元素的数量，解析失败时返回`None`
+pub fn element(input: &str) -> Option<usize> {
+    crate::element().parse(input).ok().map(|(_, el)| count(&el))
+}
+
+/// 使用`parse_xml`解析，额外检查文档只有一个根元素，返回元素的数量，解析失败时返回`None`
+pub fn limited(input: &str) -> Option<usize> {
+    parse_xml(input, &Limits::default().max_depth(MAX_DEPTH)).ok().map(|el| count(&el))
+}
@@ -0,0 +1,13 @@
+//! 基于组合器XML解析器的命令行工具，用法见`combinator::cli`
+
+use std::{env, io, process};
+
+fn main() {
+    let code = combinator::cli::run(
+        env::args().skip(1),
+        &mut io::stdin().lock(),
+        &mut io::stdout().lock(),
+        &mut io::stderr().lock(),
+    );
+    process::exit(code);
+}
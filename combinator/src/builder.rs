@@ -0,0 +1,149 @@
+//! `Element`的构建与修改
+//! 构建器方法获取所有权并返回修改后的元素，可以链式调用：
+//! `Element::new("top").attr("label", "Top").child(Element::new("bottom"))`
+//! 修改方法接受`&mut self`，属性始终保持插入时的顺序，与解析结果一致
+
+use crate::Element;
+
+impl Element {
+    /// 没有属性、子元素和文本的元素
+    pub(crate) fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), attributes: vec![], children: vec![], text: String::new() }
+    }
+
+    /// 设置属性，同名的属性已经存在时在原位置替换它的值
+    pub(crate) fn attr(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+
+    pub(crate) fn child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub(crate) fn children(mut self, children: impl IntoIterator<Item = Element>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    pub(crate) fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    /// 设置属性并返回旧值，新属性追加在末尾，已有的属性保持原来的位置
+    pub(crate) fn set_attribute(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        let name = name.into();
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, old)) => Some(std::mem::replace(old, value)),
+            None => {
+                self.attributes.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// 删除属性并返回它的值，其余属性的顺序不变
+    pub(crate) fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let index = self.attributes.iter().position(|(key, _)| key == name)?;
+        Some(self.attributes.remove(index).1)
+    }
+
+    pub(crate) fn push_child(&mut self, child: Element) {
+        self.children.push(child);
+    }
+
+    /// 在`index`处插入子元素，`index`大于子元素数量时panic
+    pub(crate) fn insert_child(&mut self, index: usize, child: Element) {
+        self.children.insert(index, child);
+    }
+
+    /// 删除并返回`index`处的子元素，`index`越界时panic
+    pub(crate) fn remove_child(&mut self, index: usize) -> Element {
+        self.children.remove(index)
+    }
+
+    /// 替换`index`处的子元素并返回被替换的元素，`index`越界时panic
+    pub(crate) fn replace_child(&mut self, index: usize, child: Element) -> Element {
+        std::mem::replace(&mut self.children[index], child)
+    }
+
+    /// 只保留满足条件的子元素
+    pub(crate) fn retain_children(&mut self, f: impl FnMut(&Element) -> bool) {
+        self.children.retain(f);
+    }
+
+    /// 修改元素名并返回旧的名称
+    pub(crate) fn rename(&mut self, name: impl Into<String>) -> String {
+        std::mem::replace(&mut self.name, name.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, element};
+
+    #[test]
+    fn build_parsed_doc() {
+        let doc = r#"
+            <top label="Top">
+                <semi-bottom label="Bottom"/>
+                <middle>
+                    <bottom label="Another bottom"/>
+                </middle>
+            </top>"#;
+        let built = Element::new("top")
+            .attr("label", "Top")
+            .child(Element::new("semi-bottom").attr("label", "Bottom"))
+            .child(
+                Element::new("middle")
+                    .child(Element::new("bottom").attr("label", "Another bottom")),
+            );
+        assert_eq!(Ok(("", built)), element().parse(doc));
+    }
+
+    #[test]
+    fn attribute_order() {
+        let mut el = Element::new("a").attr("x", "1").attr("y", "2").attr("x", "3");
+        assert_eq!(
+            vec![("x".to_owned(), "3".to_owned()), ("y".to_owned(), "2".to_owned())],
+            el.attributes
+        );
+
+        assert_eq!(None, el.set_attribute("z", "4"));
+        assert_eq!(Some("2".to_owned()), el.set_attribute("y", "5"));
+        assert_eq!(Some("3".to_owned()), el.remove_attribute("x"));
+        assert_eq!(None, el.remove_attribute("x"));
+        assert_eq!(
+            vec![("y".to_owned(), "5".to_owned()), ("z".to_owned(), "4".to_owned())],
+            el.attributes
+        );
+    }
+
+    #[test]
+    fn mutate_children() {
+        let names = |el: &Element| el.children.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        let mut el = Element::new("list").children(["b", "d"].map(Element::new));
+
+        el.insert_child(0, Element::new("a"));
+        el.insert_child(2, Element::new("c"));
+        el.push_child(Element::new("e"));
+        assert_eq!(vec!["a", "b", "c", "d", "e"], names(&el));
+
+        assert_eq!("b", el.remove_child(1).name);
+        assert_eq!("c", el.replace_child(1, Element::new("x").text("new")).name);
+        assert_eq!("new", el.children[1].text);
+        el.retain_children(|c| c.name != "e");
+        assert_eq!(vec!["a", "x", "d"], names(&el));
+
+        assert_eq!("list", el.rename("items"));
+        assert_eq!("items", el.name);
+    }
+}
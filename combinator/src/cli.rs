@@ -0,0 +1,210 @@
+//! 命令行工具`xml`的实现，`src/bin/xml.rs`只负责把参数和标准输入输出交给`run`
+//!
+//! 命令：
+//! 1. `validate [file]`：检查文档是否良构，出错时报告行号和列号
+//! 2. `format [file]`：以两个空格缩进输出文档
+//! 3. `json [--ordered] [file]`：按照`json_mapping`的规则转换为JSON，`--ordered`保持子元素的顺序
+//! 4. `query <selector> [file]`：每行输出一个匹配选择器的元素
+//!
+//! 没有给出文件或者文件为`-`时从标准输入读取，所有命令都接受`--max-depth <n>`限制元素的嵌套深度
+//! 退出码：0为成功，1为文档不合法或者查询没有结果，2为参数错误、无法读取输入或者选择器不合法
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    Element,
+    json_mapping::JsonMapping,
+    xml::{Limits, XmlError, parse_xml},
+};
+
+const SUCCESS: i32 = 0;
+const FAILURE: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+
+const USAGE: &str = "\
+usage: xml <command> [--max-depth <n>] [file]
+
+commands:
+  validate [file]           check that the document is well-formed
+  format [file]             pretty-print the document
+  json [--ordered] [file]   convert the document to JSON
+  query <selector> [file]   print the elements matching the selector
+
+Without a file, or with `-`, the document is read from stdin.
+Elements may be nested at most 128 levels deep unless `--max-depth` says otherwise.
+";
+
+enum Command {
+    Validate,
+    Format,
+    Json { ordered: bool },
+    Query { selector: String },
+}
+
+/// 执行一条命令并返回退出码，`args`不包含程序名
+pub fn run<I>(args: I, stdin: &mut dyn Read, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32
+where
+    I: IntoIterator<Item = String>,
+{
+    let (command, limits, file) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            let _ = write!(stderr, "error: {}\n\n{}", message, USAGE);
+            return USAGE_ERROR;
+        }
+    };
+
+    let (name, source) = match read_input(file.as_deref(), stdin) {
+        Ok(input) => input,
+        Err((name, err)) => {
+            let _ = writeln!(stderr, "{}: {}", name, err);
+            return USAGE_ERROR;
+        }
+    };
+
+    let root = match parse_xml(&source, &limits) {
+        Ok(root) => root,
+        Err(err) => {
+            let _ = stderr.write_all(diagnostic(&name, &source, err).as_bytes());
+            return FAILURE;
+        }
+    };
+
+    let result = match command {
+        Command::Validate => Ok(SUCCESS),
+        Command::Format => write_pretty(stdout, &root, 0).map(|_| SUCCESS),
+        Command::Json { ordered } => {
+            let mapping = if ordered {
+                JsonMapping::new().ordered_children("#children").expect("not an XML name")
+            } else {
+                JsonMapping::new()
+            };
+            writeln!(stdout, "{}", mapping.to_json(&root)).map(|_| SUCCESS)
+        }
+        Command::Query { selector } => match root.select(&selector) {
+            Ok(found) => found
+                .iter()
+                .try_for_each(|el| writeln!(stdout, "{}", el))
+                .map(|_| if found.is_empty() { FAILURE } else { SUCCESS }),
+            Err(rest) => {
+                let _ = writeln!(stderr, "error: invalid selector at `{}`", rest);
+                return USAGE_ERROR;
+            }
+        },
+    };
+    // 标准输出被关闭（例如管道的另一端已经退出）时不再报告
+    result.unwrap_or(FAILURE)
+}
+
+fn parse_args<I>(args: I) -> Result<(Command, Limits, Option<String>), String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("validate") => Command::Validate,
+        Some("format") => Command::Format,
+        Some("json") => Command::Json { ordered: false },
+        Some("query") => Command::Query { selector: String::new() },
+        Some(other) => return Err(format!("unknown command `{}`", other)),
+        None => return Err("missing command".to_owned()),
+    };
+
+    let mut operands = vec![];
+    let mut ordered = false;
+    let mut limits = Limits::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ordered" if matches!(command, Command::Json { .. }) => ordered = true,
+            "--max-depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) if depth > 0 => limits = limits.max_depth(depth),
+                _ => return Err("`--max-depth` expects a positive number".to_owned()),
+            },
+            option if option.starts_with("--") => {
+                return Err(format!("unknown option `{}`", option));
+            }
+            _ => operands.push(arg),
+        }
+    }
+
+    let command = match command {
+        Command::Json { .. } => Command::Json { ordered },
+        Command::Query { .. } if operands.is_empty() => return Err("missing selector".to_owned()),
+        Command::Query { .. } => Command::Query { selector: operands.remove(0) },
+        command => command,
+    };
+    if let Some(extra) = operands.get(1) {
+        return Err(format!("unexpected argument `{}`", extra));
+    }
+    Ok((command, limits, operands.pop()))
+}
+
+/// 读取文件或者标准输入，返回输入的名称和内容，失败时返回名称和错误
+fn read_input(
+    file: Option<&str>,
+    stdin: &mut dyn Read,
+) -> Result<(String, String), (String, io::Error)> {
+    match file {
+        None | Some("-") => {
+            let mut source = String::new();
+            match stdin.read_to_string(&mut source) {
+                Ok(_) => Ok(("<stdin>".to_owned(), source)),
+                Err(err) => Err(("<stdin>".to_owned(), err)),
+            }
+        }
+        Some(path) => match fs::read_to_string(path) {
+            Ok(source) => Ok((path.to_owned(), source)),
+            Err(err) => Err((path.to_owned(), err)),
+        },
+    }
+}
+
+/// `name:line:column: error: message`，之后是出错的那一行以及指向出错位置的`^`
+fn diagnostic(name: &str, source: &str, err: XmlError) -> String {
+    let offset = err.offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = source[offset..].find('\n').map_or(source.len(), |index| offset + index);
+    let line = source[..offset].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+
+    // 保留制表符，让`^`与终端中的显示对齐
+    let padding = source[line_start..offset]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    format!(
+        "{}:{}:{}: error: {}\n{}\n{}^\n",
+        name,
+        line,
+        column,
+        err.kind,
+        source[line_start..line_end].trim_end_matches('\r'),
+        padding
+    )
+}
+
+/// 每个元素占一行，子元素缩进两个空格，只有文本的元素写在同一行
+fn write_pretty(out: &mut dyn Write, el: &Element, depth: usize) -> io::Result<()> {
+    let indent = "  ".repeat(depth);
+    write!(out, "{}<{}", indent, el.name)?;
+    for (name, value) in &el.attributes {
+        write!(out, " {}=\"{}\"", name, value)?;
+    }
+    match (el.children.is_empty(), el.text.is_empty()) {
+        (true, true) => writeln!(out, "/>"),
+        (true, false) => writeln!(out, ">{}</{}>", el.text, el.name),
+        (false, _) => {
+            writeln!(out, ">")?;
+            if !el.text.is_empty() {
+                writeln!(out, "{}  {}", indent, el.text)?;
+            }
+            for child in &el.children {
+                write_pretty(out, child, depth + 1)?;
+            }
+            writeln!(out, "{}</{}>", indent, el.name)
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! RFC 4180 CSV解析器
+//! 每次只从`BufRead`中读取一条记录，再交给组合器解析，所以可以逐行处理很大的文件
+//! 参考：https://www.rfc-editor.org/rfc/rfc4180
+
+use std::io::{self, BufRead};
+
+use crate::{
+    Parser, any_char, either, end_of_input, left, many0, match_literal, newline, optional, pair,
+    right, zero_or_more,
+};
+
+#[derive(Debug)]
+enum CsvError {
+    Io(io::Error),
+    /// 记录的格式不正确，例如未加引号的字段中出现了引号，`line`为记录开始的行号（从1开始）
+    Syntax {
+        line: usize,
+    },
+    /// 直到输入结束，引号都没有闭合
+    UnterminatedQuote {
+        line: usize,
+    },
+    /// 记录的字段数量与第一条记录（或表头）不一致
+    FieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+/// 按记录读取CSV，每条记录是一个`Vec<String>`
+/// 空行会被跳过
+struct CsvReader<R> {
+    reader: R,
+    delimiter: char,
+    has_headers: bool,
+    headers: Option<Vec<String>>,
+    /// 表头所在的记录已经被读取，即使它读取失败也不会再被当作表头
+    headers_read: bool,
+    field_count: Option<usize>,
+    /// 已经读取的行数
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            delimiter: ',',
+            has_headers: true,
+            headers: None,
+            headers_read: false,
+            field_count: None,
+            line: 0,
+        }
+    }
+
+    /// 字段分隔符，默认为`,`
+    fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// 第一条记录是否为表头，默认为`true`，表头不会出现在迭代的结果中
+    fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// 读取表头，如果没有启用表头或者输入为空则返回`None`
+    fn headers(&mut self) -> Result<Option<&[String]>, CsvError> {
+        if self.has_headers && !self.headers_read {
+            self.headers_read = true;
+            if let Some(headers) = self.read_record().transpose()? {
+                self.headers = Some(headers);
+            }
+        }
+        Ok(self.headers.as_deref())
+    }
+
+    /// 读取一条完整的记录
+    /// 如果读到的内容中引号的数量为奇数，说明有字段跨越了多行，需要继续读取下一行
+    fn read_record(&mut self) -> Option<Result<Vec<String>, CsvError>> {
+        let mut buffer = String::new();
+        let mut line;
+
+        // 空行直接跳过，用循环而不是递归，连续的空行再多也不会耗尽栈
+        loop {
+            buffer.clear();
+            let mut quotes = 0;
+            line = self.line + 1;
+            loop {
+                let start = buffer.len();
+                match self.reader.read_line(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        self.line += 1;
+                        quotes += buffer[start..].matches('"').count();
+                        if quotes % 2 == 0 {
+                            break;
+                        }
+                    }
+                    Err(err) => return Some(Err(CsvError::Io(err))),
+                }
+            }
+
+            if buffer.is_empty() {
+                return None;
+            }
+            if quotes % 2 != 0 {
+                return Some(Err(CsvError::UnterminatedQuote { line }));
+            }
+            if !buffer.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+        }
+
+        let fields = match record(self.delimiter).parse(&buffer) {
+            Ok((_, fields)) => fields,
+            Err(_) => return Some(Err(CsvError::Syntax { line })),
+        };
+
+        let expected = *self.field_count.get_or_insert(fields.len());
+        if fields.len() != expected {
+            return Some(Err(CsvError::FieldCount { line, expected, found: fields.len() }));
+        }
+
+        Some(Ok(fields))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<Vec<String>, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(err) = self.headers() {
+            return Some(Err(err));
+        }
+        self.read_record()
+    }
+}
+
+/// record = field *(delimiter field) [CRLF]
+fn record<'a>(delimiter: char) -> impl Parser<'a, Vec<String>> {
+    let fields = pair(
+        field(delimiter),
+        zero_or_more(right(any_char.pred(move |c| *c == delimiter), field(delimiter))),
+    )
+    .map(|(first, mut rest)| {
+        rest.insert(0, first);
+        rest
+    });
+
+    left(fields, pair(optional(newline()), end_of_input()))
+}
+
+fn field<'a>(delimiter: char) -> impl Parser<'a, String> {
+    either(escaped_field(), non_escaped_field(delimiter))
+}
+
+/// 引号包裹的字段可以包含分隔符和换行，两个连续的引号表示一个引号
+fn escaped_field<'a>() -> impl Parser<'a, String> {
+    right(
+        match_literal("\""),
+        left(
+            many0(either(match_literal("\"\"").map(|_| '"'), any_char.pred(|c| *c != '"'))),
+            match_literal("\""),
+        ),
+    )
+}
+
+fn non_escaped_field<'a>(delimiter: char) -> impl Parser<'a, String> {
+    many0(any_char.pred(move |c| *c != delimiter && *c != '"' && *c != '\r' && *c != '\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+
+    use super::*;
+
+    fn rows(fields: &[&[&str]]) -> Vec<Vec<String>> {
+        fields.iter().map(|row| row.iter().map(|field| field.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn csv_parser() {
+        let doc = "name,comment\r\n\"Joe\",\"says \"\"hi\"\"\"\r\nMike,\"line one\nline two\"\r\n\r\nRobert,\n";
+        let mut reader = CsvReader::new(doc.as_bytes());
+
+        assert_eq!(Some(&rows(&[&["name", "comment"]])[0][..]), reader.headers().unwrap());
+        assert_eq!(
+            rows(&[&["Joe", "says \"hi\""], &["Mike", "line one\nline two"], &["Robert", ""]]),
+            reader.map(Result::unwrap).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn delimiter_and_headers() {
+        let doc = "1;\"a;b\"\n2;c";
+        let reader = CsvReader::new(doc.as_bytes()).delimiter(';').has_headers(false);
+        assert_eq!(
+            rows(&[&["1", "a;b"], &["2", "c"]]),
+            reader.map(Result::unwrap).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn malformed_records() {
+        let mut reader = CsvReader::new("a,b\n1,2,3\n\"x\"y,z\n\"open,4\n5,6\n".as_bytes());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(CsvError::FieldCount { line: 2, expected: 2, found: 3 }))
+        ));
+        assert!(matches!(reader.next(), Some(Err(CsvError::Syntax { line: 3 }))));
+        // 未闭合的引号会一直读取到输入结束
+        assert!(matches!(reader.next(), Some(Err(CsvError::UnterminatedQuote { line: 4 }))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn many_blank_lines() {
+        let doc = format!("a,b\n{}1,2\n", "\n".repeat(1_000_000));
+        let reader = CsvReader::new(doc.as_bytes());
+        assert_eq!(rows(&[&["1", "2"]]), reader.map(Result::unwrap).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn malformed_headers() {
+        let mut reader = CsvReader::new("\"a,b\"c\n1,2\n".as_bytes());
+        assert!(matches!(reader.headers(), Err(CsvError::Syntax { line: 1 })));
+        // 出错的表头已经被消耗，不会把下一条记录当作表头
+        assert_eq!(None, reader.headers().unwrap());
+        assert_eq!(rows(&[&["1", "2"]]), reader.map(Result::unwrap).collect::<Vec<_>>());
+    }
+
+    /// 无限重复同一条记录的输入，只有按需读取时才能从中取出记录
+    struct Endless;
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let row = b"1,\"two\"\n";
+            let len = buf.len().min(row.len());
+            buf[..len].copy_from_slice(&row[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn streaming_rows() {
+        let reader = CsvReader::new(BufReader::new(Endless)).has_headers(false);
+        assert_eq!(
+            rows(&[&["1", "two"], &["1", "two"], &["1", "two"]]),
+            reader.take(3).map(Result::unwrap).collect::<Vec<_>>()
+        );
+    }
+}
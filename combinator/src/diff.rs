@@ -0,0 +1,413 @@
+//! 两棵`Element`树之间的结构化差异
+//! 子元素先按整棵子树是否相同进行匹配，剩下的再按名称依次匹配，
+//! 匹配上的子元素如果相对顺序发生了变化则报告为移动，没有匹配上的报告为删除或插入
+//! 元素改名会被报告为删除加插入，只有根元素例外
+
+use std::fmt;
+
+use crate::Element;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct DiffOptions {
+    /// 只比较属性的名称和值，不比较它们的先后顺序
+    ignore_attribute_order: bool,
+    /// 比较文本和属性值之前去掉首尾空白，并把连续的空白视为一个空格
+    ignore_whitespace: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Change {
+    /// 删除的元素使用旧树中的路径，其他变化使用新树中的路径，格式与模式校验相同
+    path: String,
+    kind: ChangeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ChangeKind {
+    /// 只会出现在根元素上
+    Renamed {
+        old: String,
+        new: String,
+    },
+    AttributeAdded {
+        name: String,
+        value: String,
+    },
+    AttributeRemoved {
+        name: String,
+        value: String,
+    },
+    AttributeChanged {
+        name: String,
+        old: String,
+        new: String,
+    },
+    AttributesReordered {
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    TextChanged {
+        old: String,
+        new: String,
+    },
+    Inserted(Element),
+    Deleted(Element),
+    /// 相对顺序发生了变化，`from`和`to`分别是它在旧、新父元素的子元素中的位置，从0开始
+    Moved {
+        from: usize,
+        to: usize,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+    fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+fn diff(old: &Element, new: &Element, options: &DiffOptions) -> Diff {
+    let mut differ = Differ { options, changes: vec![] };
+    let path = format!("/{}", new.name);
+    if old.name != new.name {
+        differ.report(&path, ChangeKind::Renamed { old: old.name.clone(), new: new.name.clone() });
+    }
+    differ.element(old, new, &path);
+    Diff { changes: differ.changes }
+}
+
+struct Differ<'o> {
+    options: &'o DiffOptions,
+    changes: Vec<Change>,
+}
+
+impl Differ<'_> {
+    fn report(&mut self, path: &str, kind: ChangeKind) {
+        self.changes.push(Change { path: path.to_owned(), kind });
+    }
+
+    fn normalize<'s>(&self, text: &'s str) -> std::borrow::Cow<'s, str> {
+        if self.options.ignore_whitespace {
+            text.split_whitespace().collect::<Vec<_>>().join(" ").into()
+        } else {
+            text.into()
+        }
+    }
+
+    /// 按当前的选项判断两棵子树是否相同
+    fn same(&self, old: &Element, new: &Element) -> bool {
+        old.name == new.name
+            && self.same_attributes(old, new)
+            && self.normalize(&old.text) == self.normalize(&new.text)
+            && old.children.len() == new.children.len()
+            && old.children.iter().zip(&new.children).all(|(old, new)| self.same(old, new))
+    }
+
+    fn same_attributes(&self, old: &Element, new: &Element) -> bool {
+        let equal = |(a, x): &(String, String), (b, y): &(String, String)| {
+            a == b && self.normalize(x) == self.normalize(y)
+        };
+        old.attributes.len() == new.attributes.len()
+            && if self.options.ignore_attribute_order {
+                old.attributes.iter().all(|a| new.attributes.iter().any(|b| equal(a, b)))
+            } else {
+                old.attributes.iter().zip(&new.attributes).all(|(a, b)| equal(a, b))
+            }
+    }
+
+    fn element(&mut self, old: &Element, new: &Element, path: &str) {
+        self.attributes(old, new, path);
+        if self.normalize(&old.text) != self.normalize(&new.text) {
+            self.report(
+                path,
+                ChangeKind::TextChanged { old: old.text.clone(), new: new.text.clone() },
+            );
+        }
+        self.children(old, new, path);
+    }
+
+    fn attributes(&mut self, old: &Element, new: &Element, path: &str) {
+        for (name, old_value) in &old.attributes {
+            match new.attribute(name) {
+                None => self.report(
+                    path,
+                    ChangeKind::AttributeRemoved { name: name.clone(), value: old_value.clone() },
+                ),
+                Some(new_value) if self.normalize(old_value) != self.normalize(new_value) => self
+                    .report(
+                        path,
+                        ChangeKind::AttributeChanged {
+                            name: name.clone(),
+                            old: old_value.clone(),
+                            new: new_value.to_owned(),
+                        },
+                    ),
+                Some(_) => {}
+            }
+        }
+        for (name, value) in &new.attributes {
+            if old.attribute(name).is_none() {
+                self.report(
+                    path,
+                    ChangeKind::AttributeAdded { name: name.clone(), value: value.clone() },
+                );
+            }
+        }
+
+        if !self.options.ignore_attribute_order {
+            let common = |el: &Element, other: &Element| -> Vec<String> {
+                el.attributes
+                    .iter()
+                    .filter(|(name, _)| other.attribute(name).is_some())
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            };
+            let (old_order, new_order) = (common(old, new), common(new, old));
+            if old_order != new_order {
+                self.report(
+                    path,
+                    ChangeKind::AttributesReordered { old: old_order, new: new_order },
+                );
+            }
+        }
+    }
+
+    fn children(&mut self, old: &Element, new: &Element, path: &str) {
+        // 新子元素的下标 -> 旧子元素的下标
+        let mut matches: Vec<Option<usize>> = vec![None; new.children.len()];
+        let mut used = vec![false; old.children.len()];
+        let mut pair = |matches: &mut Vec<Option<usize>>, same_tree: bool| {
+            for (j, child) in new.children.iter().enumerate() {
+                if matches[j].is_some() {
+                    continue;
+                }
+                let found = old.children.iter().enumerate().position(|(i, candidate)| {
+                    !used[i]
+                        && if same_tree {
+                            self.same(candidate, child)
+                        } else {
+                            candidate.name == child.name
+                        }
+                });
+                if let Some(i) = found {
+                    used[i] = true;
+                    matches[j] = Some(i);
+                }
+            }
+        };
+        pair(&mut matches, true);
+        pair(&mut matches, false);
+
+        let stable = longest_increasing(&matches);
+
+        for (i, child) in old.children.iter().enumerate() {
+            if !matches.contains(&Some(i)) {
+                self.report(
+                    &child_path(path, &old.children, i),
+                    ChangeKind::Deleted(child.clone()),
+                );
+            }
+        }
+        for (j, child) in new.children.iter().enumerate() {
+            let child_path = child_path(path, &new.children, j);
+            match matches[j] {
+                None => self.report(&child_path, ChangeKind::Inserted(child.clone())),
+                Some(i) => {
+                    if i != j && !stable.contains(&j) {
+                        self.report(&child_path, ChangeKind::Moved { from: i, to: j });
+                    }
+                    self.element(&old.children[i], child, &child_path);
+                }
+            }
+        }
+    }
+}
+
+/// `siblings[index]`的路径，下标只在同名的兄弟元素之间计数，从1开始
+fn child_path(parent: &str, siblings: &[Element], index: usize) -> String {
+    let name = &siblings[index].name;
+    let position = siblings[..index].iter().filter(|el| el.name == *name).count() + 1;
+    format!("{}/{}[{}]", parent, name, position)
+}
+
+/// 旧下标严格递增的最长的一组匹配，返回它们在新子元素中的下标
+/// 这些子元素保持了原来的相对顺序，其余匹配上的子元素被视为移动
+/// 长度相同的序列中优先选择下标没有变化的子元素更多的一组，这样报告的移动才是真正换了位置的元素
+fn longest_increasing(matches: &[Option<usize>]) -> Vec<usize> {
+    let pairs: Vec<(usize, usize)> =
+        matches.iter().enumerate().filter_map(|(j, i)| i.map(|i| (j, i))).collect();
+    // scores[k]：以pairs[k]结尾的最长递增序列的长度以及其中下标不变的子元素数量，
+    // previous[k]：序列中的前一项
+    let unchanged = |k: usize| usize::from(pairs[k].0 == pairs[k].1);
+    let mut scores: Vec<(usize, usize)> = (0..pairs.len()).map(|k| (1, unchanged(k))).collect();
+    let mut previous = vec![None; pairs.len()];
+    for k in 0..pairs.len() {
+        for p in 0..k {
+            let score = (scores[p].0 + 1, scores[p].1 + unchanged(k));
+            if pairs[p].1 < pairs[k].1 && score > scores[k] {
+                scores[k] = score;
+                previous[k] = Some(p);
+            }
+        }
+    }
+
+    let mut result = vec![];
+    let mut current = (0..pairs.len()).max_by_key(|&k| (scores[k], std::cmp::Reverse(k)));
+    while let Some(k) = current {
+        result.push(pairs[k].0);
+        current = previous[k];
+    }
+    result
+}
+
+/// 补丁形式的输出，每个变化一行：
+/// `+`为添加，`-`为删除，`~`为修改，`>`为移动
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for Change { path, kind } in &self.changes {
+            match kind {
+                ChangeKind::Renamed { old, .. } => {
+                    writeln!(f, "~ {} renamed from <{}>", path, old)?
+                }
+                ChangeKind::AttributeAdded { name, value } => {
+                    writeln!(f, "+ {} @{}={:?}", path, name, value)?
+                }
+                ChangeKind::AttributeRemoved { name, value } => {
+                    writeln!(f, "- {} @{}={:?}", path, name, value)?
+                }
+                ChangeKind::AttributeChanged { name, old, new } => {
+                    writeln!(f, "~ {} @{}: {:?} -> {:?}", path, name, old, new)?
+                }
+                ChangeKind::AttributesReordered { old, new } => writeln!(
+                    f,
+                    "~ {} attribute order: {} -> {}",
+                    path,
+                    old.join(" "),
+                    new.join(" ")
+                )?,
+                ChangeKind::TextChanged { old, new } => {
+                    writeln!(f, "~ {} text: {:?} -> {:?}", path, old, new)?
+                }
+                ChangeKind::Inserted(el) => writeln!(f, "+ {} {}", path, el)?,
+                ChangeKind::Deleted(el) => writeln!(f, "- {} {}", path, el)?,
+                ChangeKind::Moved { from, to } => {
+                    writeln!(f, "> {} moved from {} to {}", path, from, to)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, element};
+
+    fn parse(doc: &str) -> Element {
+        element().parse(doc).unwrap().1
+    }
+
+    #[test]
+    fn identical_trees() {
+        let doc = parse(r#"<top label="Top"><middle><bottom/></middle></top>"#);
+        assert!(diff(&doc, &doc.clone(), &DiffOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn attribute_changes() {
+        let old = parse(r#"<top a="1" b="2" c="3"/>"#);
+        let new = parse(r#"<top c="3" b="two" d="4"/>"#);
+        let options = DiffOptions::default();
+        assert_eq!(
+            "- /top @a=\"1\"\n\
+             ~ /top @b: \"2\" -> \"two\"\n\
+             + /top @d=\"4\"\n\
+             ~ /top attribute order: b c -> c b\n",
+            diff(&old, &new, &options).to_string()
+        );
+
+        let options = DiffOptions { ignore_attribute_order: true, ..DiffOptions::default() };
+        assert_eq!(3, diff(&old, &new, &options).changes.len());
+        let reordered = parse(r#"<top c="3" a="1" b="2"/>"#);
+        assert!(diff(&old, &reordered, &options).is_empty());
+    }
+
+    #[test]
+    fn child_changes() {
+        let old = parse(
+            r#"
+            <top>
+                <header/>
+                <middle id="1"><bottom label="a"/></middle>
+                <middle id="2"/>
+                <footer/>
+            </top>"#,
+        );
+        let new = parse(
+            r#"
+            <top>
+                <middle id="2"/>
+                <middle id="1"><bottom label="b"/></middle>
+                <footer/>
+                <aside/>
+            </top>"#,
+        );
+        let changes = diff(&old, &new, &DiffOptions::default());
+        assert_eq!(
+            vec![
+                Change {
+                    path: "/top/header[1]".to_owned(),
+                    kind: ChangeKind::Deleted(parse("<header/>"))
+                },
+                Change {
+                    path: "/top/middle[1]".to_owned(),
+                    kind: ChangeKind::Moved { from: 2, to: 0 }
+                },
+                Change {
+                    path: "/top/middle[2]/bottom[1]".to_owned(),
+                    kind: ChangeKind::AttributeChanged {
+                        name: "label".to_owned(),
+                        old: "a".to_owned(),
+                        new: "b".to_owned()
+                    }
+                },
+                Change {
+                    path: "/top/aside[1]".to_owned(),
+                    kind: ChangeKind::Inserted(parse("<aside/>"))
+                },
+            ],
+            changes.changes
+        );
+        assert_eq!(
+            "- /top/header[1] <header/>\n\
+             > /top/middle[1] moved from 2 to 0\n\
+             ~ /top/middle[2]/bottom[1] @label: \"a\" -> \"b\"\n\
+             + /top/aside[1] <aside/>\n",
+            changes.to_string()
+        );
+    }
+
+    #[test]
+    fn whitespace() {
+        let old = Element::new("p").attr("class", "a  b").text("Hello\n  world ");
+        let new = Element::new("p").attr("class", "a b").text("Hello world");
+        assert_eq!(2, diff(&old, &new, &DiffOptions::default()).changes.len());
+        let options = DiffOptions { ignore_whitespace: true, ..DiffOptions::default() };
+        assert!(diff(&old, &new, &options).is_empty());
+    }
+
+    #[test]
+    fn renamed_root() {
+        let old = parse(r#"<config version="1"/>"#);
+        let new = parse(r#"<settings version="1"/>"#);
+        assert_eq!(
+            "~ /settings renamed from <config>\n",
+            diff(&old, &new, &DiffOptions::default()).to_string()
+        );
+    }
+}
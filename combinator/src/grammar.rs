@@ -0,0 +1,248 @@
+//! 声明语法规则的宏
+//! 用类似EBNF的写法代替手工嵌套的`right(match_literal("<"), pair(identifier, attributes()))`，
+//! 每条规则展开为一个返回`impl Parser`的函数，规则体展开为已有的组合器
+//!
+//! ```text
+//! 规则    name -> Output = 候选项 | 候选项 ... ;
+//! 候选项  项 项 ...              输出见下文
+//!         项 项 ... => { 表达式 }  用捕获的名称计算输出
+//!         项 项 ... =>? { 表达式 } 表达式返回`Option`，`None`时解析失败
+//! 项      名称:原子 或者 原子，原子之后可以跟`*`、`+`或`?`
+//! 原子    "字面量"               match_literal
+//!         rule                   调用另一条规则，每次解析时才构造，所以规则之间可以互相递归
+//!         { 表达式 }             任意的解析器表达式，例如`{identifier}`、`{space0()}`
+//!         ( 候选项 | ... )        分组
+//! ```
+//!
+//! 没有`=>`时，候选项的输出由捕获决定：只有一个项并且没有捕获时就是这个项的输出，
+//! 否则是捕获的值，多个捕获组成元组，没有捕获时为`()`
+
+/// 规则之间用`;`分隔，规则名前可以加可见性，例如`pub(crate) element -> Element = ...;`
+#[allow(unused_macros)]
+macro_rules! grammar {
+    () => {};
+
+    // 按`;`切出一条规则，同时按顶层的`|`切出候选项
+    (@rule [$vis:vis] $name:ident [$output:ty] [$($done:tt)*] [$($current:tt)*] ; $($rest:tt)*) => {
+        $vis fn $name<'a>() -> impl $crate::Parser<'a, $output> {
+            $crate::grammar::grammar!(@either $($done)* [$($current)*])
+        }
+        $crate::grammar::grammar!($($rest)*);
+    };
+    (@rule [$vis:vis] $name:ident [$output:ty] [$($done:tt)*] [$($current:tt)*] | $($rest:tt)*) => {
+        $crate::grammar::grammar!(@rule [$vis] $name [$output] [$($done)* [$($current)*]] [] $($rest)*);
+    };
+    (@rule [$vis:vis] $name:ident [$output:ty] [$($done:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::grammar::grammar!(@rule [$vis] $name [$output] [$($done)*] [$($current)* $next] $($rest)*);
+    };
+
+    (@either [$($only:tt)*]) => {
+        $crate::grammar::grammar!(@action [] $($only)*)
+    };
+    (@either [$($first:tt)*] $($rest:tt)+) => {
+        $crate::either(
+            $crate::grammar::grammar!(@action [] $($first)*),
+            $crate::grammar::grammar!(@either $($rest)+),
+        )
+    };
+
+    // 切出候选项末尾的`=>`
+    (@action [$($items:tt)*] => ? { $($body:tt)* }) => {
+        $crate::grammar::grammar!(@sequence (filter { $($body)* }) { [] [] [] } $($items)*)
+    };
+    (@action [$($items:tt)*] => { $($body:tt)* }) => {
+        $crate::grammar::grammar!(@sequence (map { $($body)* }) { [] [] [] } $($items)*)
+    };
+    (@action [$($items:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::grammar::grammar!(@action [$($items)* $next] $($rest)*)
+    };
+    (@action [$($items:tt)*]) => {
+        $crate::grammar::grammar!(@sequence (default) { [] [] [] } $($items)*)
+    };
+
+    // 逐项累积`{ [解析器] [模式] [捕获] }`，解析器用`pair`向左嵌套，模式与之对应
+    (@sequence $mode:tt $acc:tt $name:ident : $atom:tt * $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc $name [$name]
+            ($crate::zero_or_more($crate::grammar::grammar!(@atom $atom))) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $name:ident : $atom:tt + $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc $name [$name]
+            ($crate::one_or_more($crate::grammar::grammar!(@atom $atom))) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $name:ident : $atom:tt ? $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc $name [$name]
+            ($crate::optional($crate::grammar::grammar!(@atom $atom))) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $name:ident : $atom:tt $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc $name [$name]
+            ($crate::grammar::grammar!(@atom $atom)) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $atom:tt * $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc _ []
+            ($crate::zero_or_more($crate::grammar::grammar!(@atom $atom))) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $atom:tt + $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc _ []
+            ($crate::one_or_more($crate::grammar::grammar!(@atom $atom))) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $atom:tt ? $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc _ []
+            ($crate::optional($crate::grammar::grammar!(@atom $atom))) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt $atom:tt $($rest:tt)*) => {
+        $crate::grammar::grammar!(@push $mode $acc _ [] ($crate::grammar::grammar!(@atom $atom)) $($rest)*)
+    };
+    (@sequence $mode:tt $acc:tt) => {
+        $crate::grammar::grammar!(@finish $mode $acc)
+    };
+
+    (@push $mode:tt { [] [] [] } $pattern:tt [$($capture:ident)?] ($($item:tt)*) $($rest:tt)*) => {
+        $crate::grammar::grammar!(@sequence $mode { [$($item)*] [$pattern] [$($capture)?] } $($rest)*)
+    };
+    (@push $mode:tt { [$($parser:tt)*] [$($patterns:tt)*] [$($captures:ident)*] }
+        $pattern:tt [$($capture:ident)?] ($($item:tt)*) $($rest:tt)*) => {
+        $crate::grammar::grammar!(@sequence $mode {
+            [$crate::pair($($parser)*, $($item)*)]
+            [($($patterns)*, $pattern)]
+            [$($captures)* $($capture)?]
+        } $($rest)*)
+    };
+
+    (@finish (default) { [$($parser:tt)*] [_] [] }) => {
+        $($parser)*
+    };
+    (@finish (default) { [$($parser:tt)*] [$pattern:tt] [] }) => {
+        $crate::map($($parser)*, |_| ())
+    };
+    (@finish (default) { [$($parser:tt)*] [$pattern:tt] [$capture:ident] }) => {
+        $crate::map($($parser)*, |$pattern| $capture)
+    };
+    (@finish (default) { [$($parser:tt)*] [$pattern:tt] [$($captures:ident)+] }) => {
+        $crate::map($($parser)*, |$pattern| ($($captures),+))
+    };
+    (@finish (map { $($body:tt)* }) { [$($parser:tt)*] [$pattern:tt] $captures:tt }) => {
+        $crate::map($($parser)*, move |$pattern| { $($body)* })
+    };
+    (@finish (filter { $($body:tt)* }) { [$($parser:tt)*] [$pattern:tt] $captures:tt }) => {
+        $crate::map(
+            $crate::pred($crate::map($($parser)*, move |$pattern| { $($body)* }), Option::is_some),
+            Option::unwrap,
+        )
+    };
+
+    // 嵌入的表达式如果自己带有描述就使用它，否则把源码当作终结符的名称
+    (@atom { $($expr:tt)* }) => {
+        $crate::syntax::labelled({ $($expr)* }, stringify!($($expr)*))
+    };
+    (@atom ( $($alternatives:tt)* )) => {
+        $crate::grammar::grammar!(@group [] [] $($alternatives)*)
+    };
+    (@atom $rule:ident) => {
+        $crate::syntax::rule(
+            stringify!($rule),
+            |input| $crate::Parser::parse(&$rule(), input),
+            || $crate::Parser::syntax(&$rule()),
+        )
+    };
+    (@atom $literal:literal) => {
+        $crate::match_literal($literal)
+    };
+
+    // 分组内部同样按`|`切出候选项，切完之后交给`@either`
+    (@group [$($done:tt)*] [$($current:tt)*] | $($rest:tt)*) => {
+        $crate::grammar::grammar!(@group [$($done)* [$($current)*]] [] $($rest)*)
+    };
+    (@group [$($done:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::grammar::grammar!(@group [$($done)*] [$($current)* $next] $($rest)*)
+    };
+    (@group [$($done:tt)*] [$($current:tt)*]) => {
+        $crate::grammar::grammar!(@either $($done)* [$($current)*])
+    };
+
+    ($vis:vis $name:ident -> $output:ty = $($rest:tt)*) => {
+        $crate::grammar::grammar!(@rule [$vis] $name [$output] [] [] $($rest)*);
+    };
+}
+
+#[allow(unused_imports)]
+pub(crate) use grammar;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Element, Parser, any_char, identifier, quoted_string, space0, space1, syntax::Grammar,
+    };
+
+    grammar! {
+        attribute_pair -> (String, String) = name:{identifier} "=" value:{quoted_string()};
+        attributes -> Vec<(String, String)> = ({space1()} attribute:attribute_pair)*;
+        element_start -> (String, Vec<(String, String)>) = "<" name:{identifier} attributes:attributes;
+        single_element -> Element = start:element_start "/>" => {
+            Element { name: start.0, attributes: start.1, children: vec![], text: String::new() }
+        };
+        open_element -> Element = start:element_start ">" => {
+            Element { name: start.0, attributes: start.1, children: vec![], text: String::new() }
+        };
+        element -> Element = {space0()} element:(single_element | parent_element) {space0()};
+        close_element -> String = "</" name:{identifier} ">";
+        // 结束标记必须与开始标记一致
+        parent_element -> Element = open:open_element children:element* close:close_element =>? {
+            (close == open.name).then_some(Element { children, ..open })
+        };
+    }
+
+    grammar! {
+        digit -> char = {any_char.pred(|c| c.is_ascii_digit())};
+        number -> i64 = digits:digit+ => { digits.into_iter().collect::<String>().parse().unwrap() };
+        sign -> i64 = "-" => { -1 } | "+" => { 1 };
+        signed -> i64 = sign:sign? number:number => { sign.unwrap_or(1) * number };
+        list -> Vec<i64> = "[" first:signed rest:("," {space0()} value:signed)* "]" => {
+            std::iter::once(first).chain(rest).collect()
+        };
+        empty -> () = "[" "]";
+        digit_pair -> (char, char) = first:digit "," second:digit;
+    }
+
+    #[test]
+    fn xml_grammar() {
+        let doc = r#"
+            <top label="Top">
+                <semi-bottom label="Bottom"/>
+                <middle>
+                    <bottom label="Another bottom"/>
+                </middle>
+            </top>"#;
+        assert_eq!(crate::element().parse(doc), element().parse(doc));
+        assert!(element().parse(doc).is_ok());
+
+        let doc = r#"<top><bottom/></middle>"#;
+        assert!(element().parse(doc).is_err());
+    }
+
+    #[test]
+    fn described_rules() {
+        assert_eq!(
+            "list ::= \"[\" signed (\",\" S? signed)* \"]\"\n\
+             signed ::= sign? number\n\
+             sign ::= \"-\" | \"+\"\n\
+             number ::= digit+\n\
+             digit ::= any_char.pred(|c| c.is_ascii_digit())\n",
+            Grammar::new("list", &list()).to_string()
+        );
+        // 嵌入的解析器带有描述时直接使用它，没有描述时使用它的源码
+        assert_eq!(
+            r#"identifier "=" quoted_string"#,
+            attribute_pair().syntax().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn grammar_notation() {
+        assert_eq!(Ok(("", -12)), signed().parse("-12"));
+        assert_eq!(Ok(("", 7)), signed().parse("7"));
+        assert_eq!(Ok((")", vec![1, -2, 3])), list().parse("[1, -2,+3])"));
+        assert_eq!(Err("]"), list().parse("[]"));
+        assert_eq!(Ok(("", ())), empty().parse("[]"));
+        assert_eq!(Ok(("x", ('1', '2'))), digit_pair().parse("1,2x"));
+    }
+}
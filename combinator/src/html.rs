@@ -0,0 +1,385 @@
+//! 宽松的HTML解析模式，产出与XML解析器相同的`Element`树
+//! 与严格的XML模式相比，它额外接受：
+//! 1. 没有`/>`的空元素，例如`<br>`、`<img src="a.png">`
+//! 2. 不带引号或者没有值的属性，例如`<input type=text disabled>`
+//! 3. 大小写不敏感的标签名和属性名，统一转换为小写
+//! 4. 可以省略结束标签的`<p>`和`<li>`
+//!
+//! 文本会去掉首尾空白后保存在`Element::text`中，注释、`<!DOCTYPE>`和`<?...?>`会被忽略
+
+use std::rc::Rc;
+
+use crate::{
+    BoxedParser, Element, Parser, any_char, either, identifier, left, many0, match_literal,
+    one_or_more, optional, pair, right, space0, space1, starts_element, whitespace_wrap,
+    xml::{Context, Limits, limited, nested},
+    zero_or_more,
+};
+
+/// 没有内容也没有结束标签的元素
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// 元素的最大嵌套深度，超出时在那个开始标签处失败，而不是耗尽栈空间
+const MAX_DEPTH: usize = 128;
+
+/// 内容不是HTML而是原始文本的元素
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// 遇到这些元素的开始标签时，未闭合的`<p>`会被隐式地闭合
+const P_CLOSERS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "div",
+    "dl",
+    "fieldset",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+enum Node {
+    Element(Element),
+    Text(String),
+    Ignored,
+}
+
+/// 解析完整的HTML文档，根元素前后可以有空白、注释和`<!DOCTYPE>`
+pub(crate) fn parse_html(input: &str) -> Result<Element, &str> {
+    // 与XML共用深度的限制
+    let ctx = Context::new(Limits::default().max_depth(MAX_DEPTH).max_input_len(usize::MAX));
+    let misc = || zero_or_more(either(ignored(), space1().map(|_| ())));
+
+    match limited(&ctx, right(misc(), left(html_element(&ctx), misc()))).parse(input) {
+        Ok(("", element)) => Ok(element),
+        Ok((rest, _)) => Err(rest),
+        Err(err) => Err(err),
+    }
+}
+
+/// 与`element`相对应的宽松版本
+fn html_element<'a>(ctx: &Rc<Context>) -> impl Parser<'a, Element> {
+    let children = ctx.clone();
+    nested(
+        ctx,
+        starts_element,
+        start_tag().and_then(move |(name, attributes, self_closing)| {
+            let el = Element { name, attributes, children: vec![], text: String::new() };
+
+            // 宽松模式下，非空元素的`/>`同样视为自闭合
+            if self_closing || VOID_ELEMENTS.contains(&el.name.as_str()) {
+                return BoxedParser::new(move |input| Ok((input, el.clone())));
+            }
+
+            if RAW_TEXT_ELEMENTS.contains(&el.name.as_str()) {
+                return left(raw_text(el.name.clone()), close_tag(el.name.clone())).map(
+                    move |text| {
+                        let mut el = el.clone();
+                        el.text = text;
+                        el
+                    },
+                );
+            }
+
+            // 可以省略结束标签的元素，遇到父元素的结束标签时同样结束
+            let close = if el.name == "p" || el.name == "li" {
+                BoxedParser::new(optional(close_tag(el.name.clone())).map(|_| ()))
+            } else {
+                close_tag(el.name.clone()).map(|_| ())
+            };
+
+            left(zero_or_more(html_node(&children, el.name.clone())), close).map(move |nodes| {
+                let mut el = el.clone();
+                for node in nodes {
+                    match node {
+                        Node::Element(child) => el.children.push(child),
+                        Node::Text(text) => el.text.push_str(&text),
+                        Node::Ignored => {}
+                    }
+                }
+                el.text = el.text.trim().to_owned();
+                el
+            })
+        }),
+    )
+}
+
+/// 子节点：注释、元素或者文本
+/// 如果下一个开始标签会隐式地闭合`parent`，那么子节点到此为止
+fn html_node<'a>(ctx: &Rc<Context>, parent: String) -> impl Parser<'a, Node> {
+    let ctx = ctx.clone();
+    move |input: &'a str| {
+        if let Ok((_, next)) = right(match_literal("<"), tag_name()).parse(input) {
+            if closes_implicitly(&parent, &next) {
+                return Err(input);
+            }
+        }
+
+        either(
+            ignored().map(|_| Node::Ignored),
+            either(html_element(&ctx).map(Node::Element), text().map(Node::Text)),
+        )
+        .parse(input)
+    }
+}
+
+fn closes_implicitly(parent: &str, next: &str) -> bool {
+    match parent {
+        "p" => P_CLOSERS.contains(&next),
+        "li" => next == "li",
+        _ => false,
+    }
+}
+
+/// `<`、标签名、属性，以及可选的`/`和`>`
+fn start_tag<'a>() -> impl Parser<'a, (String, Vec<(String, String)>, bool)> {
+    right(
+        match_literal("<"),
+        pair(
+            tag_name(),
+            pair(
+                html_attributes(),
+                right(space0(), left(optional(match_literal("/")), match_literal(">"))),
+            ),
+        ),
+    )
+    .map(|(name, (attributes, slash))| (name, attributes, slash.is_some()))
+}
+
+fn close_tag<'a>(expected_name: String) -> impl Parser<'a, String> {
+    right(match_literal("</"), left(whitespace_wrap(tag_name()), match_literal(">")))
+        .pred(move |name| name == &expected_name)
+}
+
+fn tag_name<'a>() -> impl Parser<'a, String> {
+    identifier.map(|name| name.to_ascii_lowercase())
+}
+
+fn html_attributes<'a>() -> impl Parser<'a, Vec<(String, String)>> {
+    zero_or_more(right(space1(), html_attribute()))
+}
+
+/// 属性值可以使用双引号、单引号或者不加引号，也可以完全省略，此时值为空字符串
+fn html_attribute<'a>() -> impl Parser<'a, (String, String)> {
+    let name = one_or_more(any_char.pred(|c| !c.is_whitespace() && !"\"'>/=".contains(*c)))
+        .map(|chars| chars.into_iter().collect::<String>().to_ascii_lowercase());
+    let quoted = |quote: &'static str| {
+        right(
+            match_literal(quote),
+            left(
+                zero_or_more(any_char.pred(move |c| !quote.starts_with(*c))),
+                match_literal(quote),
+            ),
+        )
+    };
+    let unquoted = one_or_more(any_char.pred(|c| !c.is_whitespace() && !"\"'=<>`".contains(*c)));
+    let value = either(quoted("\""), either(quoted("'"), unquoted))
+        .map(|chars| decode_entities(&chars.into_iter().collect::<String>()));
+
+    pair(name, optional(right(whitespace_wrap(match_literal("=")), value)))
+        .map(|(name, value)| (name, value.unwrap_or_default()))
+}
+
+fn text<'a>() -> impl Parser<'a, String> {
+    one_or_more(any_char.pred(|c| *c != '<'))
+        .map(|chars| decode_entities(&chars.into_iter().collect::<String>()))
+}
+
+/// `<script>`和`<style>`的内容原样保留，直到遇到对应的结束标签
+fn raw_text<'a>(name: String) -> impl Parser<'a, String> {
+    move |input: &'a str| {
+        // ASCII大小写转换不会改变字节偏移
+        let end = format!("</{}", name);
+        match input.to_ascii_lowercase().find(&end) {
+            Some(index) => Ok((&input[index..], input[..index].to_owned())),
+            None => Err(input),
+        }
+    }
+}
+
+/// 注释、`<!DOCTYPE html>`以及`<?xml ...?>`
+fn ignored<'a>() -> impl Parser<'a, ()> {
+    either(
+        right(match_literal("<!--"), take_until("-->")),
+        either(
+            right(match_literal("<!"), take_until(">")),
+            right(match_literal("<?"), take_until(">")),
+        ),
+    )
+}
+
+/// 消耗到`end`为止（包括`end`）的所有输入
+fn take_until<'a>(end: &'static str) -> impl Parser<'a, ()> {
+    move |input: &'a str| match input.find(end) {
+        Some(index) => Ok((&input[index + end.len()..], ())),
+        None => Err(input),
+    }
+}
+
+/// 解码字符引用，例如`&amp;`、`&#39;`、`&#x4e2d;`，无法识别的引用原样保留
+fn decode_entities(text: &str) -> String {
+    let entity = right(
+        match_literal("&"),
+        left(
+            one_or_more(any_char.pred(|c| c.is_ascii_alphanumeric() || *c == '#')),
+            match_literal(";"),
+        ),
+    )
+    .map(|chars| {
+        let name = chars.into_iter().collect::<String>();
+        match name.as_str() {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => {
+                    name.strip_prefix('#').and_then(|dec| dec.parse().ok()).and_then(char::from_u32)
+                }
+            },
+        }
+    })
+    .pred(Option::is_some)
+    .map(Option::unwrap);
+
+    many0(either(entity, any_char)).parse(text).map(|(_, text)| text).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element;
+
+    fn el(name: &str, attributes: &[(&str, &str)], children: Vec<Element>, text: &str) -> Element {
+        Element {
+            name: name.to_owned(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            children,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn void_elements_and_attributes() {
+        let doc = r#"<div id=main class="a b" hidden><br><img src=x.png alt='a "pic"'><input disabled/></div>"#;
+        let parsed_doc = el(
+            "div",
+            &[("id", "main"), ("class", "a b"), ("hidden", "")],
+            vec![
+                el("br", &[], vec![], ""),
+                el("img", &[("src", "x.png"), ("alt", "a \"pic\"")], vec![], ""),
+                el("input", &[("disabled", "")], vec![], ""),
+            ],
+            "",
+        );
+        assert_eq!(Ok(parsed_doc), parse_html(doc));
+    }
+
+    #[test]
+    fn case_insensitive_names() {
+        let doc = r#"<UL Class="menu"><Li>One</LI></ul>"#;
+        let parsed_doc = el("ul", &[("class", "menu")], vec![el("li", &[], vec![], "One")], "");
+        assert_eq!(Ok(parsed_doc), parse_html(doc));
+    }
+
+    #[test]
+    fn implicitly_closed_elements() {
+        let doc = r#"
+            <body>
+                <ul><li>One<li>Two</ul>
+                <p>First
+                <p>Second
+                <div>Block</div>
+            </body>"#;
+        let parsed_doc = el(
+            "body",
+            &[],
+            vec![
+                el("ul", &[], vec![el("li", &[], vec![], "One"), el("li", &[], vec![], "Two")], ""),
+                el("p", &[], vec![], "First"),
+                el("p", &[], vec![], "Second"),
+                el("div", &[], vec![], "Block"),
+            ],
+            "",
+        );
+        assert_eq!(Ok(parsed_doc), parse_html(doc));
+    }
+
+    #[test]
+    fn html_document() {
+        let doc = r#"<!DOCTYPE html>
+            <!-- snapshot -->
+            <html>
+                <head>
+                    <meta charset=utf-8>
+                    <script>if (a < b && c) {}</script>
+                </head>
+                <body>Fish &amp; chips &#x4e2d;&#25991; &unknown;</body>
+            </html>
+        "#;
+        let parsed_doc = el(
+            "html",
+            &[],
+            vec![
+                el(
+                    "head",
+                    &[],
+                    vec![
+                        el("meta", &[("charset", "utf-8")], vec![], ""),
+                        el("script", &[], vec![], "if (a < b && c) {}"),
+                    ],
+                    "",
+                ),
+                el("body", &[], vec![], "Fish & chips 中文 &unknown;"),
+            ],
+            "",
+        );
+        assert_eq!(Ok(parsed_doc), parse_html(doc));
+    }
+
+    #[test]
+    fn mismatched_closing_tag() {
+        // `<b>`无法闭合，于是`<div>`在`<b>`处就找不到自己的结束标签
+        assert_eq!(Err("<b>bold</span></div>"), parse_html("<div><b>bold</span></div>"));
+    }
+
+    #[test]
+    fn deep_nesting_fails_cleanly() {
+        let doc = "<div>".repeat(MAX_DEPTH) + &"</div>".repeat(MAX_DEPTH);
+        assert!(parse_html(&doc).is_ok());
+
+        let doc = "<div>".repeat(100_000);
+        assert_eq!(Err(&doc[MAX_DEPTH * 5..]), parse_html(&doc));
+    }
+
+    #[test]
+    fn strict_mode_unchanged() {
+        assert!(element().parse("<div><br></div>").is_err());
+        assert!(element().parse("<div hidden/>").is_err());
+    }
+}
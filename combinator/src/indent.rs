@@ -0,0 +1,251 @@
+//! 缩进敏感的语法
+//! 块的范围由缩进决定，例如YAML或者Python：子块必须比父级缩进更深，
+//! 块结束后的行必须回到某一个外层块的列
+//!
+//! 缩进只计算空格，只包含空格的行不参与缩进的比较
+
+use crate::{
+    ParseResult, Parser, Span, end_of_input, left, match_literal, newline, optional, pair,
+    zero_or_more,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum IndentErrorKind {
+    /// 语法错误，区间从无法继续解析的位置开始，到该行结束为止
+    Syntax,
+    /// 缩进比上一行更深，但这里不能开始一个新的块
+    UnexpectedIndent { found: usize },
+    /// 反缩进没有回到任何一个外层块的列，`expected`为这一行可以使用的列
+    InconsistentDedent { found: usize, expected: Vec<usize> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct IndentError {
+    pub(crate) kind: IndentErrorKind,
+    pub(crate) span: Span,
+}
+
+impl IndentError {
+    /// 根据解析失败时剩下的输入判断错误的种类
+    /// 失败的位置落在某一行的缩进之中时，把这一行的缩进与前面各行打开的块比较，
+    /// 缩进相关的错误的区间是整行
+    pub(crate) fn at(source: &str, rest: &str) -> Self {
+        let offset = source.len() - rest.len();
+        let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let line = source[line_start..].split('\n').next().unwrap_or_default();
+        let (_, found) = indentation(line).unwrap_or_default();
+
+        let kind = if offset - line_start > found || line.trim().is_empty() {
+            IndentErrorKind::Syntax
+        } else {
+            let expected = open_columns(&source[..line_start]);
+            match expected.last() {
+                _ if expected.contains(&found) => IndentErrorKind::Syntax,
+                Some(&deepest) if found < deepest => {
+                    IndentErrorKind::InconsistentDedent { found, expected }
+                }
+                _ => IndentErrorKind::UnexpectedIndent { found },
+            }
+        };
+
+        let span = match kind {
+            IndentErrorKind::Syntax => Span::of(source, rest.split('\n').next().unwrap_or(rest)),
+            _ => Span::of(source, line),
+        };
+        Self { kind, span }
+    }
+}
+
+/// 在`source`的末尾仍然打开的块的列，从外到内排列
+fn open_columns(source: &str) -> Vec<usize> {
+    let mut columns = Vec::new();
+    for line in source.lines().rev().filter(|line| !line.trim().is_empty()) {
+        let (_, column) = indentation(line).unwrap_or_default();
+        if columns.last().is_none_or(|&inner| column < inner) {
+            columns.push(column);
+        }
+        if column == 0 {
+            break;
+        }
+    }
+    columns.reverse();
+    columns
+}
+
+/// 当前行开头的空格数量，不消耗输入
+pub(crate) fn indentation(input: &str) -> ParseResult<'_, usize> {
+    Ok((input, input.len() - input.trim_start_matches(' ').len()))
+}
+
+/// 恰好`width`个空格的缩进
+pub(crate) fn indent<'a>(width: usize) -> impl Parser<'a, ()> {
+    move |input: &'a str| match indentation(input)? {
+        (_, found) if found == width => Ok((&input[width..], ())),
+        _ => Err(input),
+    }
+}
+
+/// 跳过只包含空格的行，包括输入末尾没有换行符的那一行
+pub(crate) fn blank_lines<'a>() -> impl Parser<'a, ()> {
+    let blank_line = pair(zero_or_more(match_literal(" ")), newline());
+    left(zero_or_more(blank_line), optional(pair(zero_or_more(match_literal(" ")), end_of_input())))
+        .map(|_| ())
+}
+
+/// 从同一列开始的一组行，每一行用`item`解析，`item`需要消耗到行尾
+/// 遇到缩进更少的行或者输入结束时停止，缩进更深的行不属于任何一项，视为错误
+pub(crate) fn aligned_block<'a, P, A>(column: usize, item: P) -> impl Parser<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+{
+    let blank_lines = blank_lines();
+    let item = pair(indent(column), item);
+    move |input: &'a str| {
+        let (input, ()) = blank_lines.parse(input)?;
+        let (mut input, ((), first)) = item.parse(input)?;
+        let mut items = vec![first];
+
+        loop {
+            let (next_input, ()) = blank_lines.parse(input)?;
+            match indentation(next_input)? {
+                _ if next_input.is_empty() => return Ok((next_input, items)),
+                (_, found) if found < column => return Ok((next_input, items)),
+                (_, found) if found > column => return Err(next_input),
+                _ => {}
+            }
+            let (rest, ((), next)) = item.parse(next_input)?;
+            input = rest;
+            items.push(next);
+        }
+    }
+}
+
+/// 比`parent`缩进更深的块，块的列由它的第一行决定
+/// `block`根据这一列构造整个块的解析器，通常是`aligned_block`，块中的每一项可以继续嵌套
+/// 块结束后的行如果停在`parent`与块之间，就是不一致的反缩进
+pub(crate) fn indented_block<'a, F, P, A>(parent: usize, block: F) -> impl Parser<'a, A>
+where
+    F: Fn(usize) -> P,
+    P: Parser<'a, A>,
+{
+    let blank_lines = blank_lines();
+    move |input: &'a str| {
+        let (start, ()) = blank_lines.parse(input)?;
+        let (_, column) = indentation(start)?;
+        if start.is_empty() || column <= parent {
+            return Err(start);
+        }
+
+        let (rest, result) = block(column).parse(start)?;
+        match indentation(rest)? {
+            (_, found) if !rest.is_empty() && found > parent => Err(rest),
+            _ => Ok((rest, result)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any_char, either, one_or_more, recognize, right};
+
+    /// `name`或者`name:`之后跟着缩进更深的子节点
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Node {
+        name: String,
+        children: Vec<Node>,
+    }
+
+    fn tree<'a>(column: usize) -> impl Parser<'a, Vec<Node>> {
+        aligned_block(column, node(column))
+    }
+
+    /// XML的名称允许`:`，这里只用字母和数字
+    fn name<'a>() -> impl Parser<'a, String> {
+        recognize(one_or_more(any_char.pred(|c| c.is_alphanumeric()))).map(str::to_owned)
+    }
+
+    fn node<'a>(column: usize) -> impl Parser<'a, Node> {
+        let line_end = either(newline(), end_of_input());
+        let leaf = left(name(), line_end).map(|name| Node { name, children: vec![] });
+        let parent = pair(
+            left(name(), pair(match_literal(":"), newline())),
+            // `map`返回装箱的解析器，打断`tree`与`node`之间递归的类型
+            indented_block(column, |column| tree(column).map(|children| children)),
+        )
+        .map(|(name, children)| Node { name, children });
+        // 失败时`either`返回第二个解析器的错误，把可能深入子块的分支放在后面
+        either(leaf, parent)
+    }
+
+    fn leaf(name: &str) -> Node {
+        Node { name: name.to_owned(), children: vec![] }
+    }
+
+    #[test]
+    fn measure_indentation() {
+        assert_eq!(Ok(("   a", 3)), indentation("   a"));
+        assert_eq!(Ok(("a", ())), indent(3).parse("   a"));
+        assert_eq!(Err("    a"), indent(3).parse("    a"));
+        assert_eq!(Ok(("  a", ())), blank_lines().parse("\n   \r\n  a"));
+        assert_eq!(Ok(("", ())), blank_lines().parse("\n  "));
+        assert_eq!(Ok(("x", vec!['a', 'b'])), aligned_block(1, any_char).parse(" a\n\n bx"));
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let doc = "a:\n  b\n\n  c:\n      d\n      e\n  f\ng\n";
+        assert_eq!(
+            Ok((
+                "",
+                vec![
+                    Node {
+                        name: "a".to_owned(),
+                        children: vec![
+                            leaf("b"),
+                            Node { name: "c".to_owned(), children: vec![leaf("d"), leaf("e")] },
+                            leaf("f"),
+                        ],
+                    },
+                    leaf("g"),
+                ]
+            )),
+            tree(0).parse(doc)
+        );
+        // 子块必须比父级更深
+        assert_eq!(
+            Err("b\n"),
+            right(match_literal("a:\n"), indented_block(0, tree)).parse("a:\nb\n")
+        );
+    }
+
+    #[test]
+    fn indentation_errors() {
+        let doc = "a:\n    b\n  c\n";
+        let rest = tree(0).parse(doc).unwrap_err();
+        assert_eq!(
+            IndentError {
+                kind: IndentErrorKind::InconsistentDedent { found: 2, expected: vec![0, 4] },
+                span: Span { start: 9, end: 12 },
+            },
+            IndentError::at(doc, rest)
+        );
+
+        let doc = "a\n  b\n";
+        let rest = tree(0).parse(doc).unwrap_err();
+        assert_eq!(
+            IndentError {
+                kind: IndentErrorKind::UnexpectedIndent { found: 2 },
+                span: Span { start: 2, end: 5 },
+            },
+            IndentError::at(doc, rest)
+        );
+
+        let doc = "a\nb!\n";
+        let rest = tree(0).parse(doc).unwrap_err();
+        assert_eq!(
+            IndentError { kind: IndentErrorKind::Syntax, span: Span { start: 3, end: 4 } },
+            IndentError::at(doc, rest)
+        );
+    }
+}
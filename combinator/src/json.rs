@@ -0,0 +1,337 @@
+//! JSON（RFC 8259）解析器
+//! 与XML的语法一样，完全由`match_literal`、`pred`、`zero_or_more`等基础组合器搭建而成
+//! 参考：https://www.rfc-editor.org/rfc/rfc8259
+
+use std::{fmt, rc::Rc};
+
+use crate::{
+    Parser, any_char, either, left, many0, match_literal, one_or_more, optional, pair, recognize,
+    right, take_while,
+    xml::{Context, Limits, limited, nested},
+    zero_or_more,
+};
+
+/// 数组和对象的最大嵌套深度，超出时在那个`[`或`{`处失败，而不是耗尽栈空间
+const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// 使用`Vec`保存成员，保持与原文一致的顺序，也保留重复的键
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// 解析完整的JSON文本，值的前后只允许出现空白
+/// 失败时返回无法继续解析的那部分输入
+pub(crate) fn parse_json(input: &str) -> Result<JsonValue, &str> {
+    // 与XML共用深度的限制，JSON没有其他的限制
+    let ctx = Context::new(Limits::default().max_depth(MAX_DEPTH).max_input_len(usize::MAX));
+    match limited(&ctx, json_value(&ctx)).parse(input) {
+        Ok(("", value)) => Ok(value),
+        Ok((rest, _)) => Err(rest),
+        Err(err) => Err(err),
+    }
+}
+
+/// JSON中的空白只有空格、制表符、换行和回车，不能直接使用`space0`
+fn json_whitespace<'a>() -> impl Parser<'a, &'a str> {
+    take_while(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+}
+
+fn json_wrap<'a, P, A>(parser: P) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+{
+    right(json_whitespace(), left(parser, json_whitespace()))
+}
+
+fn json_value<'a>(ctx: &Rc<Context>) -> impl Parser<'a, JsonValue> {
+    let starts = |input: &str| input.starts_with(['[', '{']);
+    let container = nested(ctx, starts, either(array(ctx), object(ctx)));
+    json_wrap(either(
+        literal(),
+        either(number(), either(json_string().map(JsonValue::String), container)),
+    ))
+}
+
+fn literal<'a>() -> impl Parser<'a, JsonValue> {
+    either(
+        match_literal("null").map(|_| JsonValue::Null),
+        either(
+            match_literal("true").map(|_| JsonValue::Bool(true)),
+            match_literal("false").map(|_| JsonValue::Bool(false)),
+        ),
+    )
+}
+
+/// number = [ minus ] int [ frac ] [ exp ]
+/// 先按照语法识别出整段数字，再交给`f64::from_str`转换，超出`f64`范围的数字视为错误
+fn number<'a>() -> impl Parser<'a, JsonValue> {
+    let digit = || any_char.pred(char::is_ascii_digit);
+    let int = either(
+        match_literal("0"),
+        pair(any_char.pred(|c| matches!(c, '1'..='9')), zero_or_more(digit())).map(|_| ()),
+    );
+    let frac = pair(match_literal("."), one_or_more(digit()));
+    let exp = pair(
+        pair(
+            any_char.pred(|c| matches!(c, 'e' | 'E')),
+            optional(any_char.pred(|c| matches!(c, '+' | '-'))),
+        ),
+        one_or_more(digit()),
+    );
+
+    recognize(pair(pair(optional(match_literal("-")), int), pair(optional(frac), optional(exp))))
+        .map(|number| number.parse().unwrap_or(f64::NAN))
+        .pred(|number| number.is_finite())
+        .map(JsonValue::Number)
+}
+
+fn json_string<'a>() -> impl Parser<'a, String> {
+    // 控制字符必须转义
+    let unescaped = any_char.pred(|c| *c != '"' && *c != '\\' && *c >= ' ');
+
+    right(match_literal("\""), left(many0(either(unescaped, escape())), match_literal("\"")))
+}
+
+fn escape<'a>() -> impl Parser<'a, char> {
+    let simple = any_char.pred(|c| "\"\\/bfnrt".contains(*c)).map(|c| match c {
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c => c,
+    });
+
+    right(match_literal("\\"), either(simple, right(match_literal("u"), unicode_escape())))
+}
+
+/// `\uXXXX`只能表示基本多文种平面的字符，其余字符需要用一对代理项表示，例如`😀`
+fn unicode_escape<'a>() -> impl Parser<'a, char> {
+    let surrogate_pair = pair(
+        hex4().pred(|unit| (0xD800..0xDC00).contains(unit)),
+        right(match_literal("\\u"), hex4().pred(|unit| (0xDC00..0xE000).contains(unit))),
+    )
+    .map(|(high, low)| 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00));
+
+    // 孤立的代理项无法表示为`char`，此时`char::from_u32`返回`None`
+    either(surrogate_pair, hex4()).map(char::from_u32).pred(Option::is_some).map(Option::unwrap)
+}
+
+/// 四个十六进制数字
+fn hex4<'a>() -> impl Parser<'a, u32> {
+    let hex = || any_char.pred(char::is_ascii_hexdigit).map(|c| c.to_digit(16).unwrap_or(0));
+    pair(pair(hex(), hex()), pair(hex(), hex()))
+        .map(|((a, b), (c, d))| a << 12 | b << 8 | c << 4 | d)
+}
+
+fn array<'a>(ctx: &Rc<Context>) -> impl Parser<'a, JsonValue> {
+    // 这里必须延迟构造`json_value`，否则`json_value`与`array`会无限地相互调用
+    let ctx = ctx.clone();
+    right(
+        match_literal("["),
+        left(comma_separated(move |input| json_value(&ctx).parse(input)), match_literal("]")),
+    )
+    .map(JsonValue::Array)
+}
+
+fn object<'a>(ctx: &Rc<Context>) -> impl Parser<'a, JsonValue> {
+    let ctx = ctx.clone();
+    let member = pair(
+        json_wrap(json_string()),
+        right(match_literal(":"), move |input| json_value(&ctx).parse(input)),
+    );
+    right(match_literal("{"), left(comma_separated(member), match_literal("}")))
+        .map(JsonValue::Object)
+}
+
+/// 以逗号分隔的零个或多个元素，没有元素时允许只包含空白
+/// 逗号之后必须跟着一个元素，所以`[1,]`会解析失败
+fn comma_separated<'a, P, A>(parser: P) -> impl Parser<'a, Vec<A>>
+where
+    P: Parser<'a, A>,
+{
+    move |mut input| {
+        let mut result = Vec::new();
+
+        match parser.parse(input) {
+            Ok((next_input, first_result)) => {
+                input = next_input;
+                result.push(first_result);
+            }
+            Err(_) => {
+                return json_whitespace().parse(input).map(|(next_input, _)| (next_input, result));
+            }
+        }
+
+        while let Ok((next_input, _)) = match_literal(",").parse(input) {
+            let (next_input, next_result) = parser.parse(next_input)?;
+            input = next_input;
+            result.push(next_result);
+        }
+
+        Ok((input, result))
+    }
+}
+
+/// 序列化为紧凑的JSON文本
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(value) => write!(f, "{}", value),
+            // JSON没有NaN和无穷大，与JavaScript的`JSON.stringify`一样输出`null`
+            JsonValue::Number(value) if !value.is_finite() => f.write_str("null"),
+            JsonValue::Number(value) => write!(f, "{}", value),
+            JsonValue::String(value) => write_json_string(f, value),
+            JsonValue::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+            JsonValue::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    #[test]
+    fn json_parser() {
+        let doc = r#"
+            {
+                "name": "top",
+                "labels": ["Top", "Bottom"],
+                "depth": 2,
+                "ratio": -0.5e1,
+                "visible": true,
+                "parent": null
+            }"#;
+        let parsed_doc = JsonValue::Object(vec![
+            ("name".to_owned(), JsonValue::String("top".to_owned())),
+            (
+                "labels".to_owned(),
+                JsonValue::Array(vec![
+                    JsonValue::String("Top".to_owned()),
+                    JsonValue::String("Bottom".to_owned()),
+                ]),
+            ),
+            ("depth".to_owned(), JsonValue::Number(2.0)),
+            ("ratio".to_owned(), JsonValue::Number(-5.0)),
+            ("visible".to_owned(), JsonValue::Bool(true)),
+            ("parent".to_owned(), JsonValue::Null),
+        ]);
+        assert_eq!(Ok(parsed_doc), parse_json(doc));
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(
+            Ok(JsonValue::String("\"\\/\u{8}\u{c}\n\r\té😀".to_owned())),
+            parse_json(r#""\"\\\/\b\f\n\r\té😀""#)
+        );
+        // 孤立的代理项
+        assert!(parse_json(r#""\ud800""#).is_err());
+    }
+
+    #[test]
+    fn serialiser() {
+        let value = JsonValue::Object(vec![
+            (
+                "a".to_owned(),
+                JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Number(2.5)]),
+            ),
+            ("b\n".to_owned(), JsonValue::String("\"quoted\"\u{1}".to_owned())),
+            ("c".to_owned(), JsonValue::Object(vec![])),
+        ]);
+        assert_eq!(r#"{"a":[1,2.5],"b\n":"\"quoted\"\u0001","c":{}}"#, value.to_string());
+        let non_finite = JsonValue::Array(vec![
+            JsonValue::Number(f64::NAN),
+            JsonValue::Number(f64::INFINITY),
+            JsonValue::Number(f64::NEG_INFINITY),
+        ]);
+        assert_eq!("[null,null,null]", non_finite.to_string());
+    }
+
+    #[test]
+    fn deep_nesting_fails_cleanly() {
+        let doc = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse_json(&doc).is_ok());
+
+        // 第129个`[`超出了限制
+        let doc = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(Err(&doc[MAX_DEPTH..]), parse_json(&doc));
+        let doc = r#"{"a":"#.repeat(100_000);
+        assert_eq!(Err(&doc[MAX_DEPTH * 5..]), parse_json(&doc));
+    }
+
+    /// `fixtures/json`中的用例沿用JSONTestSuite的命名方式：
+    /// `y_`开头的文档必须被接受，`n_`开头的文档必须被拒绝，
+    /// `i_`开头的文档由实现决定，例如超出`f64`范围的数字，这里只要求不会panic
+    #[test]
+    fn conformance_suite() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/json");
+        let mut cases =
+            fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+        cases.sort();
+        assert!(!cases.is_empty());
+
+        for path in cases {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let doc = fs::read_to_string(&path).unwrap();
+
+            if name.starts_with("y_") {
+                let value = parse_json(&doc)
+                    .unwrap_or_else(|err| panic!("{}: rejected at {:?}", name, err));
+                // 序列化后再解析应当得到相同的值
+                assert_eq!(Ok(value.clone()), parse_json(&value.to_string()), "{}", name);
+            } else if name.starts_with("n_") {
+                assert!(parse_json(&doc).is_err(), "{}: accepted", name);
+            } else {
+                let _ = parse_json(&doc);
+            }
+        }
+    }
+}
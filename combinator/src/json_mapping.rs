@@ -0,0 +1,347 @@
+//! `Element`与`JsonValue`之间的转换
+//! 映射规则：
+//! 1. 元素转换为只有一个成员的对象`{"name": body}`，`body`是保存元素内容的对象
+//! 2. 属性转换为加上前缀的成员，默认前缀为`@`，例如`"@label": "Top"`
+//! 3. 非空的文本转换为名为`#text`的成员
+//! 4. 子元素默认按名称分组，每组是一个数组，例如`"bottom": [{...}, {...}]`，
+//!    分组按照每个名称第一次出现的顺序排列；
+//!    也可以保持原来的顺序，放在同一个数组中，数组的每一项是`{"name": body}`
+//!
+//! XML的名称不能以`@`或`#`开头，所以默认的配置不会与子元素的名称冲突，
+//! 修改前缀和字段名时同样要求它们不能以名称的首字符开头，否则返回`InvalidKey`
+//! `to_element`可以把`to_json`的结果还原，但分组时不同名称的子元素交错出现的顺序会丢失
+
+use std::fmt;
+
+use crate::{Element, is_name_start_char, json::JsonValue};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Children {
+    /// 同名的子元素放在以名称为键的数组中
+    Grouped,
+    /// 所有子元素按原来的顺序放在这个字段的数组中
+    Ordered(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct JsonMapping {
+    attribute_prefix: String,
+    text_field: String,
+    children: Children,
+}
+
+impl Default for JsonMapping {
+    fn default() -> Self {
+        Self {
+            attribute_prefix: "@".to_owned(),
+            text_field: "#text".to_owned(),
+            children: Children::Grouped,
+        }
+    }
+}
+
+/// 还原时遇到的不符合映射规则的值
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FromJsonError {
+    /// 以`/`分隔的元素名称和成员名，例如`top/middle/@label`
+    path: String,
+    expected: &'static str,
+}
+
+impl fmt::Display for FromJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: expected {}", self.path, self.expected)
+    }
+}
+
+impl std::error::Error for FromJsonError {}
+
+/// 无法与元素名称或者其他成员区分的前缀或字段名
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct InvalidKey {
+    key: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid key `{}`: {}", self.key, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidKey {}
+
+impl JsonMapping {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn attribute_prefix(mut self, prefix: impl Into<String>) -> Result<Self, InvalidKey> {
+        self.attribute_prefix = prefix.into();
+        self.check(&self.attribute_prefix)?;
+        Ok(self)
+    }
+
+    fn text_field(mut self, field: impl Into<String>) -> Result<Self, InvalidKey> {
+        self.text_field = field.into();
+        self.check(&self.text_field)?;
+        Ok(self)
+    }
+
+    /// 保持子元素的顺序，把它们放在`field`的数组中
+    pub(crate) fn ordered_children(mut self, field: impl Into<String>) -> Result<Self, InvalidKey> {
+        let field = field.into();
+        self.children = Children::Ordered(field.clone());
+        self.check(&field)?;
+        Ok(self)
+    }
+
+    /// 刚刚修改的`key`不能是空的，不能以名称的首字符开头，也不能与其他的前缀和字段名混淆
+    fn check(&self, key: &str) -> Result<(), InvalidKey> {
+        let invalid = |reason| Err(InvalidKey { key: key.to_owned(), reason });
+        if key.chars().next().is_none_or(is_name_start_char) {
+            return invalid("must start with a character that cannot start an XML name");
+        }
+
+        let fields = match &self.children {
+            Children::Ordered(field) => vec![&self.text_field, field],
+            Children::Grouped => vec![&self.text_field],
+        };
+        if fields.iter().any(|field| field.starts_with(self.attribute_prefix.as_str())) {
+            return invalid("conflicts with the attribute prefix");
+        }
+        if fields.len() == 2 && fields[0] == fields[1] {
+            return invalid("conflicts with the text field");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_json(&self, el: &Element) -> JsonValue {
+        JsonValue::Object(vec![(el.name.clone(), self.body(el))])
+    }
+
+    fn body(&self, el: &Element) -> JsonValue {
+        let mut members = el
+            .attributes
+            .iter()
+            .map(|(name, value)| {
+                (format!("{}{}", self.attribute_prefix, name), JsonValue::String(value.clone()))
+            })
+            .collect::<Vec<_>>();
+        if !el.text.is_empty() {
+            members.push((self.text_field.clone(), JsonValue::String(el.text.clone())));
+        }
+
+        match &self.children {
+            Children::Grouped => {
+                let mut groups: Vec<(String, Vec<JsonValue>)> = vec![];
+                for child in &el.children {
+                    let body = self.body(child);
+                    match groups.iter_mut().find(|(name, _)| *name == child.name) {
+                        Some((_, group)) => group.push(body),
+                        None => groups.push((child.name.clone(), vec![body])),
+                    }
+                }
+                members.extend(
+                    groups.into_iter().map(|(name, group)| (name, JsonValue::Array(group))),
+                );
+            }
+            Children::Ordered(field) if !el.children.is_empty() => {
+                let children = el.children.iter().map(|child| self.to_json(child)).collect();
+                members.push((field.clone(), JsonValue::Array(children)));
+            }
+            Children::Ordered(_) => {}
+        }
+        JsonValue::Object(members)
+    }
+
+    fn to_element(&self, value: &JsonValue) -> Result<Element, FromJsonError> {
+        self.element(value, "")
+    }
+
+    /// `{"name": body}`，`parent`为父元素的路径
+    fn element(&self, value: &JsonValue, parent: &str) -> Result<Element, FromJsonError> {
+        match value {
+            JsonValue::Object(members) if members.len() == 1 => {
+                let (name, body) = &members[0];
+                self.element_body(name, body, &join(parent, name))
+            }
+            _ => Err(FromJsonError {
+                path: parent.to_owned(),
+                expected: "an object with a single member",
+            }),
+        }
+    }
+
+    fn element_body(
+        &self,
+        name: &str,
+        body: &JsonValue,
+        path: &str,
+    ) -> Result<Element, FromJsonError> {
+        let JsonValue::Object(members) = body else {
+            return Err(FromJsonError { path: path.to_owned(), expected: "an object" });
+        };
+
+        let mut el = Element::new(name);
+        for (key, value) in members {
+            let member_path = join(path, key);
+            let expected = |expected| FromJsonError { path: member_path.clone(), expected };
+            if *key == self.text_field {
+                let JsonValue::String(text) = value else { return Err(expected("a string")) };
+                el.text = text.clone();
+            } else if let Some(attribute) = key.strip_prefix(self.attribute_prefix.as_str()) {
+                let JsonValue::String(value) = value else { return Err(expected("a string")) };
+                el.attributes.push((attribute.to_owned(), value.clone()));
+            } else {
+                let JsonValue::Array(values) = value else { return Err(expected("an array")) };
+                match &self.children {
+                    Children::Grouped => {
+                        for value in values {
+                            el.children.push(self.element_body(key, value, &member_path)?);
+                        }
+                    }
+                    Children::Ordered(field) if key == field => {
+                        for value in values {
+                            el.children.push(self.element(value, path)?);
+                        }
+                    }
+                    Children::Ordered(_) => return Err(expected("an attribute or the children")),
+                }
+            }
+        }
+        Ok(el)
+    }
+}
+
+fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() { name.to_owned() } else { format!("{}/{}", parent, name) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, element, json::parse_json};
+
+    const DOC: &str = include_str!("../fixtures/xml/valid.xml");
+
+    fn json(doc: &str) -> JsonValue {
+        parse_json(doc).unwrap()
+    }
+
+    #[test]
+    fn grouped_children() {
+        let (_, el) = element().parse(DOC).unwrap();
+        let mapping = JsonMapping::new();
+        let converted = json(
+            r#"{"top": {
+                "@label": "Top",
+                "semi-bottom": [{"@label": "Bottom"}],
+                "middle": [{"bottom": [{"@label": "Another bottom"}]}]
+            }}"#,
+        );
+        assert_eq!(converted, mapping.to_json(&el));
+        assert_eq!(Ok(el), mapping.to_element(&converted));
+
+        // 同名的子元素被放在一起，交错的顺序无法还原
+        let el = Element::new("a")
+            .child(Element::new("b").attr("n", "1"))
+            .child(Element::new("c"))
+            .child(Element::new("b").attr("n", "2"));
+        let converted = mapping.to_json(&el);
+        assert_eq!(r#"{"a":{"b":[{"@n":"1"},{"@n":"2"}],"c":[{}]}}"#, converted.to_string());
+        let restored = mapping.to_element(&converted).unwrap();
+        assert_eq!(
+            vec!["b", "b", "c"],
+            restored.children.iter().map(|c| &c.name).collect::<Vec<_>>()
+        );
+        assert_eq!(converted, mapping.to_json(&restored));
+    }
+
+    #[test]
+    fn ordered_children() {
+        let (_, el) = element().parse(DOC).unwrap();
+        let mapping = JsonMapping::new().ordered_children("$children").unwrap();
+        let converted = json(
+            r#"{"top": {
+                "@label": "Top",
+                "$children": [
+                    {"semi-bottom": {"@label": "Bottom"}},
+                    {"middle": {"$children": [{"bottom": {"@label": "Another bottom"}}]}}
+                ]
+            }}"#,
+        );
+        assert_eq!(converted, mapping.to_json(&el));
+        assert_eq!(Ok(el), mapping.to_element(&converted));
+    }
+
+    #[test]
+    fn text_and_prefix() {
+        let el = Element::new("p").attr("class", "note").text("hello").child(Element::new("br"));
+        let mapping = JsonMapping::new().attribute_prefix("-").unwrap().text_field("$t").unwrap();
+        let converted = mapping.to_json(&el);
+        assert_eq!(r#"{"p":{"-class":"note","$t":"hello","br":[{}]}}"#, converted.to_string());
+        assert_eq!(Ok(el), mapping.to_element(&converted));
+    }
+
+    #[test]
+    fn to_element_errors() {
+        let mapping = JsonMapping::new();
+        assert_eq!(
+            Err(FromJsonError { path: "top/middle/@label".to_owned(), expected: "a string" }),
+            mapping.to_element(&json(r#"{"top": {"middle": [{"@label": 1}]}}"#))
+        );
+        assert_eq!(
+            Err(FromJsonError { path: "top/bottom".to_owned(), expected: "an array" }),
+            mapping.to_element(&json(r#"{"top": {"bottom": {}}}"#))
+        );
+        assert_eq!(
+            Err(FromJsonError { path: String::new(), expected: "an object with a single member" }),
+            mapping.to_element(&json(r#"{"a": {}, "b": {}}"#))
+        );
+        let mapping = mapping.ordered_children("#children").unwrap();
+        assert_eq!(
+            "top/bottom: expected an attribute or the children",
+            mapping.to_element(&json(r#"{"top": {"bottom": []}}"#)).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn round_trip_with_custom_keys() {
+        let (_, el) = element().parse(DOC).unwrap();
+        let el = el.attr("t", "attribute").text("text").child(Element::new("t"));
+        let mappings = [
+            JsonMapping::new().attribute_prefix("-").unwrap().text_field("$t").unwrap(),
+            JsonMapping::new().text_field("$").unwrap().ordered_children("$c").unwrap(),
+            JsonMapping::new().text_field("-text").unwrap().attribute_prefix("#").unwrap(),
+        ];
+        for mapping in mappings {
+            let converted = json(&mapping.to_json(&el).to_string());
+            assert_eq!(Ok(&el), mapping.to_element(&converted).as_ref(), "{}", converted);
+        }
+    }
+
+    #[test]
+    fn invalid_keys() {
+        let reason = "must start with a character that cannot start an XML name";
+        for key in ["", "a", "_", "top", "中"] {
+            let invalid = InvalidKey { key: key.to_owned(), reason };
+            assert_eq!(Err(&invalid), JsonMapping::new().attribute_prefix(key).as_ref());
+            assert_eq!(Err(&invalid), JsonMapping::new().text_field(key).as_ref());
+            assert_eq!(Err(&invalid), JsonMapping::new().ordered_children(key).as_ref());
+        }
+
+        assert_eq!(
+            "invalid key `@text`: conflicts with the attribute prefix",
+            JsonMapping::new().text_field("@text").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "invalid key `#`: conflicts with the attribute prefix",
+            JsonMapping::new().attribute_prefix("#").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "invalid key `#text`: conflicts with the text field",
+            JsonMapping::new().ordered_children("#text").unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! 先分词再解析
+//! `Lexer`由一组规则构建，每条规则是一个普通的`Parser`，例如`match_literal("let")`或者
+//! `one_or_more(any_char.pred(char::is_ascii_digit))`，不需要正则表达式
+//! 每一步选择匹配最长的规则，长度相同时优先级高的规则胜出，再相同时先注册的规则胜出，
+//! 所以关键字只需要比标识符的优先级高，`letter`仍然是一个标识符
+//! 空白和注释这样的规则可以注册为跳过，它们匹配的内容不会出现在结果中
+//!
+//! 分词的结果是带有区间的`Token`序列，`Parser`以`&[Token]`为输入时，
+//! `pair`、`either`、`zero_or_more`等组合器都可以直接使用，失败时返回无法继续解析的剩余`Token`
+//! 这里只补充以`Token`为单位的基本解析器
+
+use crate::{BoxedParser, ParseResult, Parser, Span, recognize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Token<'a, K> {
+    kind: K,
+    text: &'a str,
+    span: Span,
+}
+
+/// 没有任何规则能够匹配的位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LexError {
+    offset: usize,
+}
+
+struct Rule<'a, K> {
+    /// 跳过的规则为`None`
+    kind: Option<K>,
+    priority: u16,
+    parser: BoxedParser<'a, &'a str>,
+}
+
+struct Lexer<'a, K> {
+    rules: Vec<Rule<'a, K>>,
+}
+
+impl<'a, K: Copy> Lexer<'a, K> {
+    fn new() -> Self {
+        Self { rules: vec![] }
+    }
+
+    fn token<P, A>(self, kind: K, parser: P) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+    {
+        self.rule(Some(kind), 0, parser)
+    }
+
+    /// 与其他规则匹配的长度相同时，优先级高的规则胜出
+    fn token_with_priority<P, A>(self, kind: K, priority: u16, parser: P) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+    {
+        self.rule(Some(kind), priority, parser)
+    }
+
+    /// 匹配的内容被丢弃，例如空白和注释
+    fn skip<P, A>(self, parser: P) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+    {
+        self.rule(None, 0, parser)
+    }
+
+    fn rule<P, A>(mut self, kind: Option<K>, priority: u16, parser: P) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+    {
+        self.rules.push(Rule { kind, priority, parser: BoxedParser::new(recognize(parser)) });
+        self
+    }
+
+    fn tokenize(&self, source: &'a str) -> Result<Vec<Token<'a, K>>, LexError> {
+        let mut tokens = vec![];
+        let mut input = source;
+        while !input.is_empty() {
+            // `max_by_key`在相等时取最后一个，所以倒序遍历，让先注册的规则胜出
+            // 不消耗输入的匹配会让分词停在原地，不予考虑
+            let best = self
+                .rules
+                .iter()
+                .rev()
+                .filter_map(|rule| match rule.parser.parse(input) {
+                    Ok((_, text)) if !text.is_empty() => Some((rule, text)),
+                    _ => None,
+                })
+                .max_by_key(|(rule, text)| (text.len(), rule.priority));
+
+            let Some((rule, text)) = best else {
+                return Err(LexError { offset: source.len() - input.len() });
+            };
+            if let Some(kind) = rule.kind {
+                tokens.push(Token { kind, text, span: Span::of(source, text) });
+            }
+            input = &input[text.len()..];
+        }
+        Ok(tokens)
+    }
+}
+
+/// 分词的结果作为解析器的输入
+type Tokens<'t, K> = &'t [Token<'t, K>];
+
+/// 任意一个`Token`
+fn any_token<'t, K>(input: Tokens<'t, K>) -> ParseResult<'t, &'t Token<'t, K>, Tokens<'t, K>> {
+    match input.split_first() {
+        Some((token, rest)) => Ok((rest, token)),
+        None => Err(input),
+    }
+}
+
+/// 种类为`kind`的`Token`，返回它的文本
+fn kind<'t, K: PartialEq + 't>(expected: K) -> impl Parser<'t, &'t str, Tokens<'t, K>> {
+    move |input: Tokens<'t, K>| match input.split_first() {
+        Some((token, rest)) if token.kind == expected => Ok((rest, token.text)),
+        _ => Err(input),
+    }
+}
+
+/// 只在所有的`Token`都已经消耗时成功
+fn end_of_tokens<'t, K: 't>() -> impl Parser<'t, (), Tokens<'t, K>> {
+    move |input: Tokens<'t, K>| if input.is_empty() { Ok((input, ())) } else { Err(input) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any_char, either, left, many_till, match_literal, pair, right};
+
+    /// 示例语言：`let`语句以及四则运算表达式，`#`开始的注释到行尾为止
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Kind {
+        Let,
+        Ident,
+        Number,
+        Operator,
+        Equals,
+        Semicolon,
+        LeftParen,
+        RightParen,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Expr {
+        Number(f64),
+        Variable(String),
+        Binary(char, Box<Expr>, Box<Expr>),
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Let {
+        name: String,
+        value: Expr,
+    }
+
+    fn lexer<'a>() -> Lexer<'a, Kind> {
+        let comment = pair(match_literal("#"), crate::zero_or_more(any_char.pred(|c| *c != '\n')));
+        Lexer::new()
+            .skip(crate::one_or_more(any_char.pred(|c| c.is_whitespace())))
+            .skip(comment)
+            .token_with_priority(Kind::Let, 1, match_literal("let"))
+            .token(
+                Kind::Ident,
+                pair(
+                    any_char.pred(|c| c.is_ascii_alphabetic() || *c == '_'),
+                    crate::zero_or_more(any_char.pred(|c| c.is_ascii_alphanumeric() || *c == '_')),
+                ),
+            )
+            .token(Kind::Number, crate::one_or_more(any_char.pred(char::is_ascii_digit)))
+            .token(Kind::Operator, any_char.pred(|c| "+-*/".contains(*c)))
+            .token(Kind::Equals, match_literal("="))
+            .token(Kind::Semicolon, match_literal(";"))
+            .token(Kind::LeftParen, match_literal("("))
+            .token(Kind::RightParen, match_literal(")"))
+    }
+
+    /// 同一优先级的运算符左结合
+    fn binary<'t, P>(operand: P, operators: &'static str) -> impl Parser<'t, Expr, Tokens<'t, Kind>>
+    where
+        P: Parser<'t, Expr, Tokens<'t, Kind>> + 't,
+    {
+        let operator = any_token
+            .pred(move |token| token.kind == Kind::Operator && operators.contains(token.text))
+            .map(|token| token.text);
+        move |input| {
+            let (mut input, mut expr) = operand.parse(input)?;
+            while let Ok((next_input, op)) = operator.parse(input) {
+                let (next_input, rhs) = operand.parse(next_input)?;
+                expr = Expr::Binary(op.chars().next().unwrap(), Box::new(expr), Box::new(rhs));
+                input = next_input;
+            }
+            Ok((input, expr))
+        }
+    }
+
+    fn expr<'t>() -> BoxedParser<'t, Expr, Tokens<'t, Kind>> {
+        BoxedParser::new(binary(binary(factor(), "*/"), "+-"))
+    }
+
+    fn factor<'t>() -> impl Parser<'t, Expr, Tokens<'t, Kind>> {
+        let number = kind(Kind::Number).map(|text| Expr::Number(text.parse().unwrap()));
+        let variable = kind(Kind::Ident).map(|name| Expr::Variable(name.to_owned()));
+        let group =
+            right(kind(Kind::LeftParen), left(|input| expr().parse(input), kind(Kind::RightParen)));
+        either(number, either(variable, group))
+    }
+
+    fn program<'t>() -> impl Parser<'t, Vec<Let>, Tokens<'t, Kind>> {
+        let statement = pair(
+            right(kind(Kind::Let), left(kind(Kind::Ident), kind(Kind::Equals))),
+            left(expr(), kind(Kind::Semicolon)),
+        )
+        .map(|(name, value)| Let { name: name.to_owned(), value });
+        many_till(statement, end_of_tokens())
+    }
+
+    fn binary_expr(op: char, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    #[test]
+    fn tokenize() {
+        let tokens = lexer().tokenize("let letter = 12; # let\nlet").unwrap();
+        assert_eq!(
+            vec![
+                Token { kind: Kind::Let, text: "let", span: Span { start: 0, end: 3 } },
+                Token { kind: Kind::Ident, text: "letter", span: Span { start: 4, end: 10 } },
+                Token { kind: Kind::Equals, text: "=", span: Span { start: 11, end: 12 } },
+                Token { kind: Kind::Number, text: "12", span: Span { start: 13, end: 15 } },
+                Token { kind: Kind::Semicolon, text: ";", span: Span { start: 15, end: 16 } },
+                Token { kind: Kind::Let, text: "let", span: Span { start: 23, end: 26 } },
+            ],
+            tokens
+        );
+        assert_eq!(Err(LexError { offset: 8 }), lexer().tokenize("let x = $;"));
+
+        // 优先级相同时先注册的规则胜出
+        let lexer = Lexer::new().token(1, match_literal("a")).token(2, any_char);
+        assert_eq!(
+            vec![1, 2],
+            lexer.tokenize("ab").unwrap().iter().map(|t| t.kind).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn token_parser() {
+        let source = "let x = 1 + 2 * (y - 3);\n# comment\nlet z = x / 4;";
+        let tokens = lexer().tokenize(source).unwrap();
+        assert_eq!(
+            Ok((
+                &[][..],
+                vec![
+                    Let {
+                        name: "x".to_owned(),
+                        value: binary_expr(
+                            '+',
+                            Expr::Number(1.0),
+                            binary_expr(
+                                '*',
+                                Expr::Number(2.0),
+                                binary_expr('-', Expr::Variable("y".to_owned()), Expr::Number(3.0)),
+                            ),
+                        ),
+                    },
+                    Let {
+                        name: "z".to_owned(),
+                        value: binary_expr('/', Expr::Variable("x".to_owned()), Expr::Number(4.0)),
+                    },
+                ]
+            )),
+            program().parse(&tokens)
+        );
+
+        // 失败时剩余的第一个`Token`指出源码中的位置，也就是缺少操作数的`)`
+        let source = "let x = 1;\nlet y = (2 + );";
+        let tokens = lexer().tokenize(source).unwrap();
+        let rest = program().parse(&tokens).unwrap_err();
+        assert_eq!(Span { start: 24, end: 25 }, rest[0].span);
+        // 缺少`;`时在输入的结尾出错
+        assert_eq!(Err(&[][..]), program().parse(&tokens[..4]));
+    }
+}
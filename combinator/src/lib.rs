@@ -4,10 +4,110 @@
 
 #![allow(dead_code)]
 
-type ParseResult<'a, Output> = Result<(&'a str, Output), &'a str>;
+mod async_read;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+mod builder;
+pub mod cli;
+mod csv;
+mod diff;
+mod grammar;
+mod html;
+mod indent;
+mod json;
+mod json_mapping;
+mod lexer;
+mod pratt;
+mod property;
+mod query;
+mod schema;
+mod serde_element;
+mod stateful;
+mod streaming;
+mod syntax;
+mod toml;
+mod trace;
+mod xml;
+mod yaml;
 
-trait Parser<'a, Output> {
-    fn parse(&self, input: &'a str) -> ParseResult<'a, Output>;
+use std::{
+    cell::OnceCell,
+    marker::PhantomData,
+    rc::{Rc, Weak},
+};
+
+use streaming::Needed;
+use syntax::{Described, Syntax};
+use trace::Named;
+use xml::{Context, Limits, XmlErrorKind, cut, limited, nested};
+
+/// 解析器的输入：源码文本`&str`，或者分词之后的`&[Token]`这样的切片
+/// 解析失败时返回出错处的剩余输入，所以输入必须可以复制
+trait Input: Copy {
+    /// 输入在内存中的地址，`trace`用它把剩余输入换算为偏移，只有文本输入需要提供
+    fn address(&self) -> Option<usize> {
+        None
+    }
+
+    /// 分块的输入在末尾还无法做出判断时，失败返回的剩余输入带有还需要的输入量，
+    /// 这不是真正的失败，`either`、`optional`和重复的组合器不会因此尝试别的分支，而是原样返回
+    fn needed(&self) -> Option<Needed> {
+        None
+    }
+
+    /// 运行之后可能回溯的一次尝试，失败时撤销它对输入之外的状态所做的修改，
+    /// 只有带状态的输入`stateful::Stateful`需要，`either`、`optional`和重复的组合器都经过这里
+    fn attempt<O>(
+        self,
+        parse: impl FnOnce(Self) -> Result<(Self, O), Self>,
+    ) -> Result<(Self, O), Self> {
+        parse(self)
+    }
+}
+
+impl Input for &str {
+    fn address(&self) -> Option<usize> {
+        Some(self.as_ptr() as usize)
+    }
+}
+
+impl<T> Input for &[T] {}
+
+/// 文本输入：完整的`&str`，或者分块到达、后面可能还有数据的`streaming::Partial`
+/// 基础解析器通过它读取文本，在分块输入的末尾还无法做出判断时返回`incomplete`
+trait Text<'a>: Input {
+    fn as_str(&self) -> &'a str;
+
+    /// 跳过开头的`len`个字节
+    fn advance(self, len: usize) -> Self;
+
+    /// 后面是否可能还有更多的输入
+    fn is_partial(&self) -> bool {
+        false
+    }
+
+    /// 在末尾失败，至少还需要`needed`的输入，只有分块的输入会这样失败
+    fn incomplete(self, _needed: Needed) -> Self {
+        self
+    }
+}
+
+impl<'a> Text<'a> for &'a str {
+    fn as_str(&self) -> &'a str {
+        self
+    }
+
+    fn advance(self, len: usize) -> Self {
+        &self[len..]
+    }
+}
+
+type ParseResult<'a, Output, I = &'a str> = Result<(I, Output), I>;
+
+/// 输入的类型默认为`&str`，所有的组合器对任何`Input`都适用
+trait Parser<'a, Output, I: Input = &'a str> {
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I>;
 
     // /// 如果可以这样做，那么所有的parser都可以使用`parser.map`的方式使用`map`方法
     // /// 这样在使用上可以更加直观，方便
@@ -21,9 +121,10 @@ trait Parser<'a, Output> {
     //         Err(err) => Err(err),
     //     }
     // }
-    fn map<F, NewOutput>(self, map_fn: F) -> BoxedParser<'a, NewOutput>
+    fn map<F, NewOutput>(self, map_fn: F) -> BoxedParser<'a, NewOutput, I>
     where
         Self: Sized + 'a,
+        I: 'a,
         Output: 'a,
         NewOutput: 'a,
         F: Fn(Output) -> NewOutput + 'a,
@@ -31,35 +132,59 @@ trait Parser<'a, Output> {
         BoxedParser::new(map(self, map_fn))
     }
 
-    fn pred<F>(self, pred_fn: F) -> BoxedParser<'a, Output>
+    fn pred<F>(self, pred_fn: F) -> BoxedParser<'a, Output, I>
     where
         Self: Sized + 'a,
+        I: 'a,
         Output: 'a,
         F: Fn(&Output) -> bool + 'a,
     {
         BoxedParser::new(pred(self, pred_fn))
     }
 
-    fn and_then<F, NextParser, NewOutput>(self, f: F) -> BoxedParser<'a, NewOutput>
+    fn and_then<F, NextParser, NewOutput>(self, f: F) -> BoxedParser<'a, NewOutput, I>
     where
         Self: Sized + 'a,
+        I: 'a,
         Output: 'a,
         NewOutput: 'a,
-        NextParser: Parser<'a, NewOutput> + 'a,
+        NextParser: Parser<'a, NewOutput, I> + 'a,
         F: Fn(Output) -> NextParser + 'a,
     {
         BoxedParser::new(and_then(self, f))
     }
+
+    /// 给解析器起一个名字，在`trace::trace`运行期间记录它的每一次尝试，
+    /// 在语法描述中它是一条同名的规则
+    fn named(self, name: &'static str) -> Named<Self>
+    where
+        Self: Sized,
+    {
+        Named::new(self, name)
+    }
+
+    /// 给没有描述的叶子附加一份语法描述，用来导出EBNF和铁路图，见`syntax`
+    fn describe(self, syntax: fn() -> Syntax) -> Described<Self>
+    where
+        Self: Sized,
+    {
+        Described::new(self, syntax)
+    }
+
+    /// 解析器接受的语法，组合器由子解析器的描述组合而成，闭包没有描述
+    fn syntax(&self) -> Option<Syntax> {
+        None
+    }
 }
 
-impl<'a, F, Output> Parser<'a, Output> for F
+impl<'a, F, Output, I: Input> Parser<'a, Output, I> for F
 where
-    F: Fn(&'a str) -> ParseResult<'a, Output>,
+    F: Fn(I) -> ParseResult<'a, Output, I>,
 {
     /// parse的返回值： ParseResult<Output>
     /// 必须与F的返回值： ParseResult<'a, Output>拥有相同的生命周期.
     /// 也就是要将这两个返回值的生命周期相关联: 'a
-    fn parse(&self, input: &'a str) -> ParseResult<'a, Output> {
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
         self(input)
     }
 }
@@ -69,6 +194,26 @@ struct Element {
     name: String,
     attributes: Vec<(String, String)>,
     children: Vec<Element>,
+    /// 元素直接包含的文本，XML语法不解析文本，所以只有HTML模式会填充它
+    text: String,
+}
+
+/// 紧凑的XML形式，不包含文本的元素可以被`element`原样解析回来
+impl std::fmt::Display for Element {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for (name, value) in &self.attributes {
+            write!(f, " {}=\"{}\"", name, value)?;
+        }
+        if self.children.is_empty() && self.text.is_empty() {
+            return f.write_str("/>");
+        }
+        write!(f, ">{}", self.text)?;
+        for child in &self.children {
+            write!(f, "{}", child)?;
+        }
+        write!(f, "</{}>", self.name)
+    }
 }
 
 // fn the_letter_a(input: &str) -> Result<(&str, ()), &str> {
@@ -77,7 +222,7 @@ struct Element {
 //         _ => Err(input),
 //     }
 // }
-fn the_letter_a(input: &str) -> ParseResult<()> {
+fn the_letter_a(input: &str) -> ParseResult<'_, ()> {
     match input.chars().next() {
         Some('a') => Ok((&input['a'.len_utf8()..], ())),
         _ => Err(input),
@@ -100,10 +245,29 @@ fn the_letter_a(input: &str) -> ParseResult<()> {
 //         _ => Err(input)
 //     }
 // }
-fn match_literal<'a>(expected: &'static str) -> impl Parser<'a, ()> {
-    move |input: &'a str| match input.get(0..expected.len()) {
-        Some(next) if next == expected => Ok((&input[expected.len()..], ())),
-        _ => Err(input),
+fn match_literal<'a, I: Text<'a>>(expected: &'static str) -> impl Parser<'a, (), I> {
+    Literal { expected }
+}
+
+struct Literal {
+    expected: &'static str,
+}
+
+/// 分块的输入是`expected`的前缀时还无法判断，还差的字节数就是需要的输入量
+impl<'a, I: Text<'a>> Parser<'a, (), I> for Literal {
+    fn parse(&self, input: I) -> ParseResult<'a, (), I> {
+        let text = input.as_str();
+        match text.get(0..self.expected.len()) {
+            Some(next) if next == self.expected => Ok((input.advance(self.expected.len()), ())),
+            _ if input.is_partial() && self.expected.starts_with(text) => {
+                Err(input.incomplete(Needed::Size(self.expected.len() - text.len())))
+            }
+            _ => Err(input),
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::literal(self.expected))
     }
 }
 
@@ -130,28 +294,47 @@ fn match_literal<'a>(expected: &'static str) -> impl Parser<'a, ()> {
 //     let next_index = matched.len();
 //     Ok((&input[next_index..], matched))
 // }
-/// 元素名称标志符的规则: 首位是字母, 后跟零个或多个字母, 数字或-
-fn identifier(input: &str) -> ParseResult<String> {
-    let mut matched = String::new();
-    let mut chars = input.chars();
-
-    match chars.next() {
-        // 第一个是字母
-        Some(next) if next.is_alphabetic() => matched.push(next),
-        _ => return Err(input),
-    }
-
-    while let Some(next) = chars.next() {
-        // 字母或者数字
-        if next.is_alphanumeric() || next == '-' {
-            matched.push(next);
-        } else {
-            break;
+/// 元素名称标志符的规则遵循XML规范中的Name: 首位是NameStartChar, 后跟零个或多个NameChar
+/// 参考：https://www.w3.org/TR/xml/#NT-Name
+fn identifier<'a, I: Text<'a>>(input: I) -> ParseResult<'a, String, I> {
+    let text = input.as_str();
+    match text.chars().next() {
+        // 第一个是字母、`_`或`:`
+        Some(first) if is_name_start_char(first) => {
+            // 额外允许数字、`-`、`.`以及组合用的符号
+            // 先找到名称的结尾，再一次性复制，避免逐个字符地`push`
+            let (next_input, _) =
+                take_while(is_name_char).parse(input.advance(first.len_utf8()))?;
+            Ok((next_input, text[..text.len() - next_input.as_str().len()].to_owned()))
         }
+        None if input.is_partial() => Err(input.incomplete(Needed::Size(1))),
+        _ => Err(input),
     }
+}
 
-    let next_index = matched.len();
-    Ok((&input[next_index..], matched))
+fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}'
+    )
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}'
+        )
 }
 
 // /// 解析器组合器
@@ -186,23 +369,44 @@ fn identifier(input: &str) -> ParseResult<String> {
 // }
 /// 解析器组合器
 /// 将两个解析器作为输入并返回一个新的解析器，并按照顺序解析它们
-fn pair<'a, P1, P2, R1, R2>(parser1: P1, parser2: P2) -> impl Parser<'a, (R1, R2)>
+fn pair<'a, P1, P2, R1, R2, I>(parser1: P1, parser2: P2) -> impl Parser<'a, (R1, R2), I>
 where
-    P1: Parser<'a, R1>,
-    P2: Parser<'a, R2>,
+    I: Input,
+    P1: Parser<'a, R1, I>,
+    P2: Parser<'a, R2, I>,
 {
-    move |input| match parser1.parse(input) {
-        Ok((next_input, result1)) => match parser2.parse(next_input) {
-            Ok((final_input, result2)) => Ok((final_input, (result1, result2))),
-            Err(err) => Err(err),
-        },
-        Err(err) => Err(err),
-    }
-
     // `.map`函数消耗自身`self`，所以parser2需要被消耗掉
     // 但`and_then`函数传入的参数是`Fn`而不是`FnOnce`，所以不允许消耗parser2
     // 所以这两者之间存在冲突，导致是个方法无法使用
     // parser1.and_then(|result1| parser2.map(move |result2| (result1.clone(), result2)))
+    Pair { parser1, parser2 }
+}
+
+struct Pair<P1, P2> {
+    parser1: P1,
+    parser2: P2,
+}
+
+impl<'a, P1, P2, R1, R2, I> Parser<'a, (R1, R2), I> for Pair<P1, P2>
+where
+    I: Input,
+    P1: Parser<'a, R1, I>,
+    P2: Parser<'a, R2, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, (R1, R2), I> {
+        match self.parser1.parse(input) {
+            Ok((next_input, result1)) => match self.parser2.parse(next_input) {
+                Ok((final_input, result2)) => Ok((final_input, (result1, result2))),
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// 任何一边没有描述时，整个序列都无法描述
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::sequence([self.parser1.syntax()?, self.parser2.syntax()?]))
+    }
 }
 
 // /// 这个解析器组合器目的是：改变结果的类型
@@ -234,31 +438,56 @@ where
 /// 这个解析器组合器目的是：改变结果的类型
 /// 例如有一个解析器返回((), String), 但你希望能够将其返回类型修改为String
 /// 这种模式在Haskell以及范畴论(category theory)中被称为"函子(functor)"
-fn map<'a, P, F, A, B>(parser: P, map_fn: F) -> impl Parser<'a, B>
+fn map<'a, P, F, A, B, I>(parser: P, map_fn: F) -> impl Parser<'a, B, I>
 where
-    P: Parser<'a, A>,
+    I: Input,
+    P: Parser<'a, A, I>,
     F: Fn(A) -> B,
 {
-    move |input| match parser.parse(input) {
-        Ok((next_input, result)) => Ok((next_input, map_fn(result))),
-        Err(err) => Err(err),
+    Map { parser, map_fn, result: PhantomData }
+}
+
+struct Map<P, F, A> {
+    parser: P,
+    map_fn: F,
+    /// `A`只出现在约束中，需要在类型中占一个位置
+    result: PhantomData<fn(A)>,
+}
+
+impl<'a, P, F, A, B, I> Parser<'a, B, I> for Map<P, F, A>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    F: Fn(A) -> B,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, B, I> {
+        match self.parser.parse(input) {
+            Ok((next_input, result)) => Ok((next_input, (self.map_fn)(result))),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
     }
 }
 
 /// left组合器
-fn left<'a, P1, P2, R1, R2>(parser1: P1, parser2: P2) -> impl Parser<'a, R1>
+fn left<'a, P1, P2, R1, R2, I>(parser1: P1, parser2: P2) -> impl Parser<'a, R1, I>
 where
-    P1: Parser<'a, R1>,
-    P2: Parser<'a, R2>,
+    I: Input,
+    P1: Parser<'a, R1, I>,
+    P2: Parser<'a, R2, I>,
 {
     map(pair(parser1, parser2), |(left, _right)| left)
 }
 
 /// right组合器
-fn right<'a, P1, P2, R1, R2>(parser1: P1, parser2: P2) -> impl Parser<'a, R2>
+fn right<'a, P1, P2, R1, R2, I>(parser1: P1, parser2: P2) -> impl Parser<'a, R2, I>
 where
-    P1: Parser<'a, R1>,
-    P2: Parser<'a, R2>,
+    I: Input,
+    P1: Parser<'a, R1, I>,
+    P2: Parser<'a, R2, I>,
 {
     map(pair(parser1, parser2), |(_left, right)| right)
 }
@@ -266,30 +495,13 @@ where
 /// 在我们得到第一个可选属性对之前我们必须处理一些事情：空格
 /// 需要处理一个或多个空格，因为<element attributes="value"/>也是一个合法的语法，即使它的空格很多
 /// 编写一个组合器来表示一个或多个解析器
-fn one_or_more<'a, P, A>(parser: P) -> impl Parser<'a, Vec<A>>
+fn one_or_more<'a, P, A, I>(parser: P) -> impl Parser<'a, Vec<A>, I>
 where
-    P: Parser<'a, A>,
+    I: Input,
+    P: Parser<'a, A, I>,
 {
-    move |mut input| {
-        let mut result = Vec::new();
-
-        match parser.parse(input) {
-            // 解析第一个元素，如果不存在，则返回一个错误
-            Ok((next_input, first_result)) => {
-                input = next_input;
-                result.push(first_result);
-            }
-            Err(err) => return Err(err),
-        }
-
-        // 尽可能多的解析元素，直到解析失败
-        while let Ok((next_input, next_result)) = parser.parse(input) {
-            input = next_input;
-            result.push(next_result);
-        }
-
-        Ok((input, result))
-    }
+    // 重复的循环只在`fold_many0`和`fold_many1`中实现一次
+    many1::<_, _, Vec<_>, _>(parser)
 }
 // /// 在我们得到第一个可选属性对之前我们必须处理一些事情：空格
 // /// 需要处理一个或多个空格，因为<element attributes="value"/>也是一个合法的语法，即使它的空格很多
@@ -307,25 +519,152 @@ where
 // }
 
 /// 支持解析零次或多次的解析器
-fn zero_or_more<'a, P, A>(parser: P) -> impl Parser<'a, Vec<A>>
+fn zero_or_more<'a, P, A, I>(parser: P) -> impl Parser<'a, Vec<A>, I>
 where
-    P: Parser<'a, A>,
+    I: Input,
+    P: Parser<'a, A, I>,
 {
-    move |mut input| {
-        let mut result = Vec::new();
+    many0::<_, _, Vec<_>, _>(parser)
+}
+
+/// 把每一次解析的结果折叠进累加器，不需要构建中间的`Vec`
+/// 每次运行解析器时都会调用`init`创建新的初始值
+fn fold_many0<'a, P, A, B, N, F, I>(parser: P, init: N, fold: F) -> impl Parser<'a, B, I>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    N: Fn() -> B,
+    F: Fn(B, A) -> B,
+{
+    FoldMany { parser, init, fold, at_least_one: false, item: PhantomData }
+}
+
+/// 与`fold_many0`相同，但至少要成功一次
+fn fold_many1<'a, P, A, B, N, F, I>(parser: P, init: N, fold: F) -> impl Parser<'a, B, I>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    N: Fn() -> B,
+    F: Fn(B, A) -> B,
+{
+    FoldMany { parser, init, fold, at_least_one: true, item: PhantomData }
+}
+
+struct FoldMany<P, N, F, A> {
+    parser: P,
+    init: N,
+    fold: F,
+    at_least_one: bool,
+    item: PhantomData<fn(A)>,
+}
 
-        // 尽可能多的解析元素，直到解析失败
-        while let Ok((next_input, next_result)) = parser.parse(input) {
+impl<'a, P, N, F, A, B, I> Parser<'a, B, I> for FoldMany<P, N, F, A>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    N: Fn() -> B,
+    F: Fn(B, A) -> B,
+{
+    fn parse(&self, mut input: I) -> ParseResult<'a, B, I> {
+        let mut acc = (self.init)();
+        if self.at_least_one {
+            let (next_input, first) = self.parser.parse(input)?;
             input = next_input;
-            result.push(next_result);
+            acc = (self.fold)(acc, first);
+        }
+        loop {
+            match input.attempt(|input| self.parser.parse(input)) {
+                Ok((next_input, item)) => {
+                    input = next_input;
+                    acc = (self.fold)(acc, item);
+                }
+                // 分块的输入到了末尾，无法知道后面是否还有更多的项
+                Err(rest) if rest.needed().is_some() => return Err(rest),
+                Err(_) => return Ok((input, acc)),
+            }
         }
+    }
 
-        Ok((input, result))
+    fn syntax(&self) -> Option<Syntax> {
+        let inner = self.parser.syntax()?;
+        Some(if self.at_least_one {
+            Syntax::one_or_more(inner)
+        } else {
+            Syntax::zero_or_more(inner)
+        })
+    }
+}
+
+/// 与`zero_or_more`相同，但结果收集到任意实现了`Default`和`Extend`的容器中，
+/// 例如把字符直接收集为`String`，或者把属性直接收集为`HashMap`
+fn many0<'a, P, A, C, I>(parser: P) -> impl Parser<'a, C, I>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    C: Default + Extend<A>,
+{
+    fold_many0(parser, C::default, extend_one)
+}
+
+/// 与`one_or_more`相同，但结果收集到任意实现了`Default`和`Extend`的容器中
+fn many1<'a, P, A, C, I>(parser: P) -> impl Parser<'a, C, I>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    C: Default + Extend<A>,
+{
+    fold_many1(parser, C::default, extend_one)
+}
+
+/// 重复`parser`直到`end`成功，返回`parser`的所有结果
+/// 两者都失败时返回`parser`的错误，例如语句列表之后应当是输入的结尾，
+/// 某条语句在中途出错时报告语句内部的位置，而不是这条语句的开头
+fn many_till<'a, P, E, A, B, I>(parser: P, end: E) -> impl Parser<'a, Vec<A>, I>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    E: Parser<'a, B, I>,
+{
+    move |mut input: I| {
+        let mut result = Vec::new();
+        loop {
+            match input.attempt(|input| end.parse(input)) {
+                Ok((next_input, _)) => return Ok((next_input, result)),
+                Err(rest) if rest.needed().is_some() => return Err(rest),
+                Err(_) => {}
+            }
+            let (next_input, item) = parser.parse(input)?;
+            input = next_input;
+            result.push(item);
+        }
+    }
+}
+
+fn extend_one<A, C: Extend<A>>(mut container: C, item: A) -> C {
+    container.extend(Some(item));
+    container
+}
+
+/// 消耗满足条件的字符，返回消耗的那一段输入，不分配内存
+/// 相当于`recognize(zero_or_more(any_char.pred(..)))`，但不需要构建中间的`Vec`
+/// 分块的输入一直满足条件到末尾时，后面可能还有满足条件的字符，所以还无法判断
+fn take_while<'a, F, I>(predicate: F) -> impl Parser<'a, &'a str, I>
+where
+    I: Text<'a>,
+    F: Fn(char) -> bool,
+{
+    move |input: I| {
+        let text = input.as_str();
+        match text.find(|c| !predicate(c)) {
+            Some(index) => Ok((input.advance(index), &text[..index])),
+            None if input.is_partial() => Err(input.incomplete(Needed::Unknown)),
+            None => Ok((input.advance(text.len()), text)),
+        }
     }
 }
 
 /// 只要输入中还剩下一个字符，它就返回一个字符
-fn any_char(input: &str) -> ParseResult<char> {
+fn any_char(input: &str) -> ParseResult<'_, char> {
     match input.chars().next() {
         Some(next) => Ok((&input[next.len_utf8()..], next)),
         _ => Err(input),
@@ -335,19 +674,38 @@ fn any_char(input: &str) -> ParseResult<char> {
 /// 谓词组合器
 /// 我们调用解析器，然后在解析器成功时对值调用谓词函数
 /// 只有当返回 true 时我们才真正返回成功，否则我们返回与解析失败一样多的错误
-fn pred<'a, P, A, F>(parser: P, predicate: F) -> impl Parser<'a, A>
+fn pred<'a, P, A, F, I>(parser: P, predicate: F) -> impl Parser<'a, A, I>
 where
-    P: Parser<'a, A>,
+    I: Input,
+    P: Parser<'a, A, I>,
     F: Fn(&A) -> bool,
 {
-    move |input| {
-        if let Ok((next_input, result)) = parser.parse(input) {
-            if predicate(&result) {
-                return Ok((next_input, result));
-            }
-        }
+    Pred { parser, predicate }
+}
 
-        Err(input)
+struct Pred<P, F> {
+    parser: P,
+    predicate: F,
+}
+
+impl<'a, P, F, A, I> Parser<'a, A, I> for Pred<P, F>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+    F: Fn(&A) -> bool,
+{
+    /// 被拒绝的结果对状态的修改同样会被撤销
+    fn parse(&self, input: I) -> ParseResult<'a, A, I> {
+        input.attempt(|input| match self.parser.parse(input) {
+            Ok((next_input, result)) if (self.predicate)(&result) => Ok((next_input, result)),
+            Err(rest) if rest.needed().is_some() => Err(rest),
+            _ => Err(input),
+        })
+    }
+
+    /// 语法描述不表达谓词，与被检查的解析器相同
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
     }
 }
 
@@ -356,21 +714,22 @@ fn whitespace_char<'a>() -> impl Parser<'a, char> {
     pred(any_char, |c| c.is_whitespace())
 }
 
-/// 一个或多个空白
-fn space1<'a>() -> impl Parser<'a, Vec<char>> {
-    one_or_more(whitespace_char())
+/// 一个或多个空白，返回消耗的空白
+fn space1<'a, I: Text<'a>>() -> impl Parser<'a, &'a str, I> {
+    pred(take_while(char::is_whitespace), |space: &&str| !space.is_empty())
+        .describe(|| Syntax::terminal("S"))
 }
 
-/// 零个或多个空白
-fn space0<'a>() -> impl Parser<'a, Vec<char>> {
-    zero_or_more(whitespace_char())
+/// 零个或多个空白，返回消耗的空白
+fn space0<'a, I: Text<'a>>() -> impl Parser<'a, &'a str, I> {
+    take_while(char::is_whitespace).describe(|| Syntax::optional(Syntax::terminal("S")))
 }
 
 /// 带引号的字符串，去掉引号并取回引号中间的值
 /// 1. 一个引号
 /// 2. 后跟零个或多个非引号的字符
 /// 3. 接着是另一个引号
-fn quoted_string<'a>() -> impl Parser<'a, String> {
+fn quoted_string<'a, I: Text<'a>>() -> impl Parser<'a, String, I> {
     // map(
     //     // 保留右边，也就是left
     //     right(
@@ -382,92 +741,234 @@ fn quoted_string<'a>() -> impl Parser<'a, String> {
     //     |chars| chars.into_iter().collect(),
     // )
 
-    right(
-        match_literal("\""),
-        left(zero_or_more(any_char.pred(|c| *c != '"')), match_literal("\"")),
-    )
-    .map(|chars| chars.into_iter().collect())
+    // 直接截取引号之间的内容，只分配一次`String`
+    let content =
+        take_while(|c| c != '"').describe(|| Syntax::zero_or_more(Syntax::terminal("[^\"]")));
+    map(right(match_literal("\""), left(content, match_literal("\""))), str::to_owned)
+        .named("quoted_string")
 }
 
 /// 属性解析器
-fn attribute_pair<'a>() -> impl Parser<'a, (String, String)> {
+fn attribute_pair<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, (String, String), I> {
     // 去掉=，获取attribute元组
-    pair(identifier, right(match_literal("="), quoted_string()))
+    let value = right(match_literal("="), cut(ctx, attribute_value(ctx)));
+    pair(element_name(ctx), value).named("attribute_pair")
+}
+
+/// 属性值，长度超出限制是致命错误
+fn attribute_value<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, String, I> {
+    let ctx = ctx.clone();
+    let value =
+        pred(consumed(quoted_string()), move |(source, value)| ctx.check_value(value, source));
+    map(value, |(_, value)| value)
 }
 
 /// 一个或多个属性的解析器
-fn attributes<'a>() -> impl Parser<'a, Vec<(String, String)>> {
+/// 属性的数量超出限制、同一个元素中出现重名的属性都是致命错误
+fn attributes<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, Vec<(String, String)>, I> {
     // 不要忘记添加attribute之间的空格（至少有一个空格）
-    zero_or_more(right(space1(), attribute_pair()))
+    let attributes = consumed(zero_or_more(right(space1(), consumed(attribute_pair(ctx)))));
+    let ctx = ctx.clone();
+    let attributes = pred(attributes, move |(source, attributes)| {
+        if !ctx.check_attributes(attributes.len(), source) {
+            return false;
+        }
+        // 重复时报告第二次出现的属性名
+        attributes.iter().enumerate().all(|(index, (source, (name, _)))| {
+            !attributes[..index].iter().any(|(_, (existing, _))| existing == name)
+                || ctx.fail(XmlErrorKind::DuplicateAttribute, source)
+        })
+    });
+    map(attributes, |(_, attributes)| {
+        attributes.into_iter().map(|(_, attribute)| attribute).collect()
+    })
+    .named("attributes")
+}
+
+/// XML语法中的名称，在语法描述中是终结符`Name`
+/// 直到分隔符为止都属于名称，如果`identifier`没能完整地消耗它，说明其中包含非法字符，这是致命错误
+/// 分块的输入中还没有出现分隔符时，名称可能还没有结束，至少还需要一个字节
+fn element_name<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, String, I> {
+    let ctx = ctx.clone();
+    let name =
+        move |input: I| {
+            let text = input.as_str();
+            let token_len = match text.find(|c: char| c.is_whitespace() || "/>=\"'<".contains(c)) {
+                Some(token_len) => token_len,
+                None if input.is_partial() => return Err(input.incomplete(Needed::Size(1))),
+                None => text.len(),
+            };
+
+            match identifier(input) {
+                Ok((next_input, name)) if name.len() == token_len => {
+                    if ctx.check_name(&name, text) { Ok((next_input, name)) } else { Err(input) }
+                }
+                _ if token_len > 0 => {
+                    ctx.fail(XmlErrorKind::InvalidName, text);
+                    Err(input)
+                }
+                _ => Err(input),
+            }
+        };
+    name.describe(|| Syntax::terminal("Name"))
 }
 
 /// < and element_name and attributes
-fn element_start<'a>() -> impl Parser<'a, (String, Vec<(String, String)>)> {
-    right(match_literal("<"), pair(identifier, attributes()))
+fn element_start<'a, I: Text<'a>>(
+    ctx: &Rc<Context>,
+) -> impl Parser<'a, (String, Vec<(String, String)>), I> {
+    right(match_literal("<"), pair(element_name(ctx), attributes(ctx))).named("element_start")
 }
 
 /// 为单个元素创建一个解析器
-fn single_element<'a>() -> impl Parser<'a, Element> {
-    left(element_start(), match_literal("/>")).map(|(name, attributes)| Element {
+fn single_element<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, Element, I> {
+    map(left(element_start(ctx), match_literal("/>")), |(name, attributes)| Element {
         name,
         attributes,
         children: vec![],
+        text: String::new(),
     })
+    .named("single_element")
 }
 
-fn open_element<'a>() -> impl Parser<'a, Element> {
-    left(element_start(), match_literal(">")).map(|(name, attributes)| Element {
+fn open_element<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, Element, I> {
+    map(left(element_start(ctx), match_literal(">")), |(name, attributes)| Element {
         name,
         attributes,
         children: vec![],
+        text: String::new(),
     })
+    .named("open_element")
 }
 
-fn either<'a, P1, P2, A>(parser1: P1, parser2: P2) -> impl Parser<'a, A>
+fn either<'a, P1, P2, A, I>(parser1: P1, parser2: P2) -> impl Parser<'a, A, I>
 where
-    P1: Parser<'a, A>,
-    P2: Parser<'a, A>,
+    I: Input,
+    P1: Parser<'a, A, I>,
+    P2: Parser<'a, A, I>,
 {
-    move |input| match parser1.parse(input) {
-        ok @ Ok(_) => ok,
-        Err(_) => parser2.parse(input),
+    Either { parser1, parser2 }
+}
+
+struct Either<P1, P2> {
+    parser1: P1,
+    parser2: P2,
+}
+
+impl<'a, P1, P2, A, I> Parser<'a, A, I> for Either<P1, P2>
+where
+    I: Input,
+    P1: Parser<'a, A, I>,
+    P2: Parser<'a, A, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, A, I> {
+        // 第一个解析器还无法判断时不能尝试第二个，否则会在数据到齐之前选错分支
+        match input.attempt(|input| self.parser1.parse(input)) {
+            Err(rest) if rest.needed().is_none() => self.parser2.parse(input),
+            result => result,
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::choice([self.parser1.syntax()?, self.parser2.syntax()?]))
     }
 }
 
+/// 使用默认的`Limits`，嵌套深度等超出限制时在出错的位置失败
 fn element<'a>() -> impl Parser<'a, Element> {
-    whitespace_wrap(either(single_element(), parent_element()))
+    element_with(&Context::new(Limits::default()))
 }
 
-/// 结束标记的解析器
-fn close_element<'a>(expected_name: String) -> impl Parser<'a, String> {
-    // 因为`right`整个是需要返回的，所以所有权必须转移出去
-    // 也就是说`expected_name`的所有权也必须一起转移出去
-    right(match_literal("</"), left(identifier, match_literal(">")))
-        .pred(move |name| name == &expected_name)
+/// 整个语法共用`ctx`，`parse_xml`由它取回错误的种类
+fn element_with<'a>(ctx: &Rc<Context>) -> impl Parser<'a, Element> {
+    // 整个语法只构造一次，子元素通过`recursive`引用正在构造的`element`
+    let element = recursive("element", |element| {
+        let element = either(single_element(ctx), parent_element(ctx, element));
+        whitespace_wrap(nested(ctx, starts_element, element)).named("element")
+    });
+    limited(ctx, element)
 }
 
-fn parent_element<'a>() -> impl Parser<'a, Element> {
-    // pair(open_element(), left(zero_or_more(element()), close_element(..oops)))
+/// 结束标记不是新的元素
+fn starts_element(input: &str) -> bool {
+    input.starts_with('<') && !input.starts_with("</")
+}
 
-    open_element().and_then(|el| {
-        left(zero_or_more(element()), close_element(el.name.clone())).map(move |children| {
-            // 这里必须使用`clone`，因为`Fn`闭包不能改变它们捕获的变量，所以无法直接给`el.
-            // children`赋值
-            let mut el = el.clone();
-            el.children = children;
-            el
-        })
-    })
+/// 结束标记的解析器，返回标记中的名称
+fn close_element<'a, I: Text<'a>>(ctx: &Rc<Context>) -> impl Parser<'a, String, I> {
+    let name = cut(ctx, left(element_name(ctx), match_literal(">")));
+    right(match_literal("</"), name).named("close_element")
+}
+
+fn parent_element<'a, P>(ctx: &Rc<Context>, element: P) -> impl Parser<'a, Element>
+where
+    P: Parser<'a, Element>,
+{
+    let children = zero_or_more(element);
+    // 没有子元素时，结束标记之前的空白不会被子元素的`whitespace_wrap`消耗，例如`<a> </a>`
+    let close = right(space0(), consumed(close_element(ctx)));
+    // 结束标记必须与开始标记一致，否则是在结束标记处的语法错误
+    let open = pair(open_element(ctx), children);
+    let ctx = ctx.clone();
+    let parent = verify_pair(open, close, move |(el, _), (close, name)| {
+        el.name == *name || ctx.fail(XmlErrorKind::Syntax, close)
+    });
+    map(parent, |((el, children), _)| Element { children, ..el }).named("parent_element")
+}
+
+/// 与`pair`相同，但两个结果必须通过`check`，否则在第二个解析器开始的位置失败，
+/// 例如结束标记必须与开始标记的名称一致
+fn verify_pair<'a, P1, P2, R1, R2, F, I>(
+    parser1: P1,
+    parser2: P2,
+    check: F,
+) -> impl Parser<'a, (R1, R2), I>
+where
+    I: Input,
+    P1: Parser<'a, R1, I>,
+    P2: Parser<'a, R2, I>,
+    F: Fn(&R1, &R2) -> bool,
+{
+    VerifyPair { pair: Pair { parser1, parser2 }, check }
+}
+
+struct VerifyPair<P1, P2, F> {
+    pair: Pair<P1, P2>,
+    check: F,
+}
+
+impl<'a, P1, P2, R1, R2, F, I> Parser<'a, (R1, R2), I> for VerifyPair<P1, P2, F>
+where
+    I: Input,
+    P1: Parser<'a, R1, I>,
+    P2: Parser<'a, R2, I>,
+    F: Fn(&R1, &R2) -> bool,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, (R1, R2), I> {
+        let (next_input, result1) = self.pair.parser1.parse(input)?;
+        match self.pair.parser2.parse(next_input) {
+            Ok((final_input, result2)) if (self.check)(&result1, &result2) => {
+                Ok((final_input, (result1, result2)))
+            }
+            Ok(_) => Err(next_input),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.pair.syntax()
+    }
 }
 
 /// 如果你有一个`Thing<A>`，并且你有一个可用的`and_then`函数
 /// 你可以将一个函数从`A`传递给`Thing<B>`，这样现在你就有了一个新的`Thing<B>`，这是一个单子
 /// `map`被称为函子，它将结果进行第二次转换
 /// `and_then`被称为单子，它将执行的函数进行第二次转换（链式执行函数）
-fn and_then<'a, P, F, A, B, NextP>(parser: P, f: F) -> impl Parser<'a, B>
+fn and_then<'a, P, F, A, B, NextP, I>(parser: P, f: F) -> impl Parser<'a, B, I>
 where
-    P: Parser<'a, A>,
-    NextP: Parser<'a, B>,
+    I: Input,
+    P: Parser<'a, A, I>,
+    NextP: Parser<'a, B, I>,
     F: Fn(A) -> NextP,
 {
     move |input| match parser.parse(input) {
@@ -486,32 +987,189 @@ where
     right(space0(), left(parser, space0()))
 }
 
+/// 可选的解析器，解析失败时不消耗输入并返回`None`
+fn optional<'a, P, A, I>(parser: P) -> impl Parser<'a, Option<A>, I>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+{
+    Optional { parser }
+}
+
+struct Optional<P> {
+    parser: P,
+}
+
+impl<'a, P, A, I> Parser<'a, Option<A>, I> for Optional<P>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, Option<A>, I> {
+        match input.attempt(|input| self.parser.parse(input)) {
+            Ok((next_input, result)) => Ok((next_input, Some(result))),
+            Err(rest) if rest.needed().is_some() => Err(rest),
+            Err(_) => Ok((input, None)),
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::optional(self.parser.syntax()?))
+    }
+}
+
+/// 丢弃解析器的结果，取回它所消耗的那一段输入
+/// 适合数字这类先校验语法，再整体转换的场景
+fn recognize<'a, P, A, I>(parser: P) -> impl Parser<'a, &'a str, I>
+where
+    I: Text<'a>,
+    P: Parser<'a, A, I>,
+{
+    map(consumed(parser), |(recognized, _)| recognized)
+}
+
+/// 同时取回解析结果以及它所消耗的那一段输入，配合`Span::of`可以得到结果在源码中的位置
+fn consumed<'a, P, A, I>(parser: P) -> impl Parser<'a, (&'a str, A), I>
+where
+    I: Text<'a>,
+    P: Parser<'a, A, I>,
+{
+    Consumed { parser }
+}
+
+struct Consumed<P> {
+    parser: P,
+}
+
+impl<'a, P, A, I> Parser<'a, (&'a str, A), I> for Consumed<P>
+where
+    I: Text<'a>,
+    P: Parser<'a, A, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, (&'a str, A), I> {
+        match self.parser.parse(input) {
+            Ok((next_input, result)) => {
+                let (text, rest) = (input.as_str(), next_input.as_str());
+                Ok((next_input, (&text[..text.len() - rest.len()], result)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
+    }
+}
+
+/// 换行符，同时接受`\n`和`\r\n`
+fn newline<'a, I: Text<'a>>() -> impl Parser<'a, (), I> {
+    map(pair(optional(match_literal("\r")), match_literal("\n")), |_| ())
+}
+
+/// 只在输入已经全部消耗时成功
+fn end_of_input<'a>() -> impl Parser<'a, ()> {
+    move |input: &'a str| if input.is_empty() { Ok((input, ())) } else { Err(input) }
+}
+
+/// 源码中的一段区间，使用字节偏移表示，左闭右开
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+impl Span {
+    /// `slice`必须是`source`的一部分，例如`consumed`取回的输入，或者解析失败时返回的剩余输入
+    fn of(source: &str, slice: &str) -> Self {
+        let start = slice.as_ptr() as usize - source.as_ptr() as usize;
+        Self { start, end: start + slice.len() }
+    }
+}
+
 /// 'a不单止与Parser<'a>相关联，也与dyn Parser<'a, Output>相关联
 /// 这使我们能够将解析器函数放入Box中，并且BoxedParser将像函数一样用作解析器
 /// 这意味着将装箱的解析器移动到堆中并且必须取消引用指针才能到达它，这可能会花费我们几个宝贵的纳秒
 /// 因此我们实际上可能想要推迟装箱所有内容。只需装箱一些比较流行的组合器就足够了
-struct BoxedParser<'a, Output> {
+struct BoxedParser<'a, Output, I: Input = &'a str> {
     /// 因为trait对象可以包含引用，所以这些引用的生存期需要表示为trait对象的一部分
-    parser: Box<dyn Parser<'a, Output> + 'a>,
+    parser: Box<dyn Parser<'a, Output, I> + 'a>,
 }
 
-impl<'a, Output> BoxedParser<'a, Output> {
+impl<'a, Output, I: Input> BoxedParser<'a, Output, I> {
     fn new<P>(parser: P) -> Self
     where
-        P: Parser<'a, Output> + 'a,
+        P: Parser<'a, Output, I> + 'a,
     {
         Self { parser: Box::new(parser) }
     }
 }
 
-impl<'a, Output> Parser<'a, Output> for BoxedParser<'a, Output> {
-    fn parse(&self, input: &'a str) -> ParseResult<'a, Output> {
+impl<'a, Output, I: Input> Parser<'a, Output, I> for BoxedParser<'a, Output, I> {
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
         self.parser.parse(input)
     }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
+    }
+}
+
+type RecursiveCell<'a, Output, I> = OnceCell<BoxedParser<'a, Output, I>>;
+
+/// 递归的语法：`build`收到一个引用正在构造的解析器自身的解析器，
+/// 整个语法只构造一次，而不是每次递归都重新构造一遍
+/// 在语法描述中，递归的引用是一条名为`name`的规则
+fn recursive<'a, P, F, Output, I>(name: &'static str, build: F) -> Recursive<'a, Output, I>
+where
+    I: Input + 'a,
+    Output: 'a,
+    P: Parser<'a, Output, I> + 'a,
+    F: FnOnce(RecursiveRef<'a, Output, I>) -> P,
+{
+    let cell = Rc::new(RecursiveCell::new());
+    let parser = build(RecursiveRef { name, cell: Rc::downgrade(&cell) });
+    let _ = cell.set(BoxedParser::new(parser));
+    Recursive { cell }
+}
+
+struct Recursive<'a, Output, I: Input> {
+    cell: Rc<RecursiveCell<'a, Output, I>>,
+}
+
+/// 只持有弱引用，否则解析器会通过自己引用自己，永远不会被释放
+struct RecursiveRef<'a, Output, I: Input> {
+    name: &'static str,
+    cell: Weak<RecursiveCell<'a, Output, I>>,
+}
+
+impl<'a, Output, I: Input> Parser<'a, Output, I> for Recursive<'a, Output, I> {
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
+        self.cell.get().expect("recursive parser is built").parse(input)
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.cell.get()?.syntax()
+    }
+}
+
+impl<'a, Output, I: Input> Parser<'a, Output, I> for RecursiveRef<'a, Output, I> {
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
+        let cell = self.cell.upgrade().expect("recursive parser is alive");
+        cell.get().expect("recursive parser is built").parse(input)
+    }
+
+    /// 规则的定义由外层的`Recursive`提供，这里只是引用
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::rule(self.name, None))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use smallvec::SmallVec;
+
     use super::*;
 
     #[test]
@@ -532,6 +1190,17 @@ mod tests {
         assert_eq!(Err("!not at all an identifier"), identifier("!not at all an identifier"));
     }
 
+    #[test]
+    fn identifier_name_chars() {
+        assert_eq!(Ok(("=", "xml:lang".to_owned())), identifier("xml:lang="));
+        assert_eq!(Ok(("", "_private.v2".to_owned())), identifier("_private.v2"));
+        assert_eq!(Ok(("", "中文名称".to_owned())), identifier("中文名称"));
+        // 数字、`-`和`.`不能出现在首位
+        assert_eq!(Err("2nd"), identifier("2nd"));
+        assert_eq!(Err(".hidden"), identifier(".hidden"));
+        assert_eq!(Ok(("$b", "a".to_owned())), identifier("a$b"));
+    }
+
     #[test]
     fn pair_combinator() {
         let tag_opener = pair(match_literal("<"), identifier);
@@ -578,6 +1247,43 @@ mod tests {
         assert_eq!(Ok(("", vec![])), parser.parse(""));
     }
 
+    #[test]
+    fn fold_combinators() {
+        let digit = || any_char.pred(char::is_ascii_digit).map(|c| c.to_digit(10).unwrap());
+        let number = fold_many1(digit(), || 0, |acc, digit| acc * 10 + digit);
+        assert_eq!(Ok(("x", 1234)), number.parse("1234x"));
+        assert_eq!(Err("x"), number.parse("x"));
+        assert_eq!(Ok(("x", 0)), fold_many0(digit(), || 0, |acc, digit| acc + digit).parse("x"));
+    }
+
+    #[test]
+    fn collect_into_containers() {
+        let word = many1::<_, _, String, _>(any_char.pred(|c| c.is_alphabetic()));
+        assert_eq!(Ok((" b", "中文a".to_owned())), word.parse("中文a b"));
+        assert_eq!(Err("1"), word.parse("1"));
+
+        let attributes = many0::<_, _, HashMap<String, String>, _>(right(
+            space1(),
+            attribute_pair(&Context::new(Limits::default())),
+        ));
+        let (rest, map) = attributes.parse(r#" a="1" b="2"/>"#).unwrap();
+        assert_eq!("/>", rest);
+        assert_eq!(Some("2"), map.get("b").map(String::as_str));
+        assert_eq!(2, map.len());
+
+        let small = many0::<_, _, SmallVec<[char; 4]>, _>(whitespace_char());
+        assert_eq!(Ok(("x", SmallVec::from_slice(&[' ', '\n']))), small.parse(" \nx"));
+    }
+
+    #[test]
+    fn many_till_combinator() {
+        let parser = many_till(right(match_literal("a"), any_char), end_of_input());
+        assert_eq!(Ok(("", vec!['1', '2'])), parser.parse("a1a2"));
+        // 第二项在`a`之后出错，错误指向那里而不是第二项的开头
+        assert_eq!(Err(""), parser.parse("a1a"));
+        assert_eq!(Err("b"), parser.parse("b"));
+    }
+
     #[test]
     fn predicate_combinator() {
         let parser = pred(any_char, |c| *c == 'o');
@@ -585,19 +1291,41 @@ mod tests {
         assert_eq!(Err("lol"), parser.parse("lol"));
     }
 
+    #[test]
+    fn take_while_combinator() {
+        let parser = take_while(char::is_alphabetic);
+        assert_eq!(Ok((" 1", "中文ab")), parser.parse("中文ab 1"));
+        assert_eq!(Ok(("1", "")), parser.parse("1"));
+        assert_eq!(Ok(("", "all")), parser.parse("all"));
+        assert_eq!(Err("x"), space1().parse("x"));
+    }
+
     #[test]
     fn quoted_string_parser() {
         assert_eq!(Ok(("", "Hello Joe!".to_owned())), quoted_string().parse("\"Hello Joe!\""));
+        assert_eq!(Ok(("", "中文".to_owned())), quoted_string().parse("\"中文\""));
+        assert_eq!(Err(""), quoted_string().parse("\"unterminated"));
     }
 
     #[test]
     fn attribute_parser() {
         assert_eq!(
             Ok(("", vec![("one".to_owned(), "1".to_owned()), ("two".to_owned(), "2".to_owned())])),
-            attributes().parse(" one=\"1\" two=\"2\"")
+            attributes(&Context::new(Limits::default())).parse(" one=\"1\" two=\"2\"")
         );
     }
 
+    #[test]
+    fn duplicate_attributes() {
+        // `attributes`自己拒绝重名的属性，错误记录在第二次出现的属性名处
+        let ctx = Context::new(Limits::default());
+        assert!(attributes(&ctx).parse(r#" a="1" b="2" a="3""#).is_err());
+        assert_eq!(Some(XmlErrorKind::DuplicateAttribute), ctx.error_kind());
+        assert_eq!(Err(r#"a="3"/>"#), element().parse(r#"<x a="1" b="2" a="3"/>"#));
+        // 名称只是前缀相同时不算重复
+        assert!(element().parse(r#"<x a="1" ab="2"/>"#).is_ok());
+    }
+
     #[test]
     fn single_element_parser() {
         assert_eq!(
@@ -606,10 +1334,11 @@ mod tests {
                 Element {
                     name: "div".to_owned(),
                     attributes: vec![("class".to_owned(), "float".to_owned())],
-                    children: vec![]
+                    children: vec![],
+                    text: String::new(),
                 }
             )),
-            single_element().parse("<div class=\"float\"/>")
+            single_element(&Context::new(Limits::default())).parse("<div class=\"float\"/>")
         );
     }
 
@@ -630,6 +1359,7 @@ mod tests {
                     name: "semi-bottom".to_string(),
                     attributes: vec![("label".to_string(), "Bottom".to_string())],
                     children: vec![],
+                    text: String::new(),
                 },
                 Element {
                     name: "middle".to_string(),
@@ -638,13 +1368,24 @@ mod tests {
                         name: "bottom".to_string(),
                         attributes: vec![("label".to_string(), "Another bottom".to_string())],
                         children: vec![],
+                        text: String::new(),
                     }],
+                    text: String::new(),
                 },
             ],
+            text: String::new(),
         };
         assert_eq!(Ok(("", parsed_doc)), element().parse(doc))
     }
 
+    #[test]
+    fn display_element() {
+        let doc =
+            r#"<top label="Top"><semi-bottom label="Bottom"/><middle><bottom/></middle></top>"#;
+        let (_, parsed_doc) = element().parse(doc).unwrap();
+        assert_eq!(doc, parsed_doc.to_string());
+    }
+
     #[test]
     fn mismatched_closing_tag() {
         let doc = r#"
@@ -653,4 +1394,16 @@ mod tests {
             </middle>"#;
         assert_eq!(Err("</middle>"), element().parse(doc));
     }
+
+    #[test]
+    fn whitespace_before_closing_tag() {
+        let empty = Element {
+            name: "a".to_owned(),
+            attributes: vec![],
+            children: vec![],
+            text: String::new(),
+        };
+        assert_eq!(Ok(("", empty)), element().parse("<a> \n</a>"));
+        assert!(element().parse("<a><b/> </a>").is_ok());
+    }
 }
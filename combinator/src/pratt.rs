@@ -0,0 +1,296 @@
+//! 运算符优先级解析器（Pratt parser）
+//! 先注册一个原子解析器，再注册前缀、中缀（左结合或右结合）以及后缀运算符，
+//! 最终得到一个产出用户自定义AST的解析器
+//! 参考：https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
+
+use crate::{BoxedParser, ParseResult, Parser, whitespace_wrap};
+
+/// 中缀运算符的结合性
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Assoc {
+    Left,
+    Right,
+}
+
+struct Prefix<'a, E> {
+    op: BoxedParser<'a, ()>,
+    right_bp: u16,
+    build: Box<dyn Fn(E) -> E + 'a>,
+}
+
+struct Infix<'a, E> {
+    op: BoxedParser<'a, ()>,
+    left_bp: u16,
+    right_bp: u16,
+    build: Box<dyn Fn(E, E) -> E + 'a>,
+}
+
+struct Postfix<'a, E> {
+    op: BoxedParser<'a, ()>,
+    left_bp: u16,
+    build: Box<dyn Fn(E) -> E + 'a>,
+}
+
+/// 表达式解析器的构建器
+/// 绑定力（binding power）越大，运算符结合得越紧密
+/// 同一位置上的运算符按注册顺序尝试，所以`**`这类较长的运算符应当先于`*`注册
+/// 原子和运算符两侧的空白会被自动忽略
+struct PrattParser<'a, E> {
+    atom: BoxedParser<'a, E>,
+    prefix: Vec<Prefix<'a, E>>,
+    infix: Vec<Infix<'a, E>>,
+    postfix: Vec<Postfix<'a, E>>,
+}
+
+impl<'a, E: 'a> PrattParser<'a, E> {
+    fn new<P>(atom: P) -> Self
+    where
+        P: Parser<'a, E> + 'a,
+    {
+        Self {
+            atom: BoxedParser::new(whitespace_wrap(atom)),
+            prefix: vec![],
+            infix: vec![],
+            postfix: vec![],
+        }
+    }
+
+    /// 注册前缀运算符，例如`-x`、`!x`
+    fn prefix<P, A, F>(mut self, op: P, bp: u8, build: F) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+        F: Fn(E) -> E + 'a,
+    {
+        self.prefix.push(Prefix {
+            op: operator(op),
+            right_bp: u16::from(bp) * 2,
+            build: Box::new(build),
+        });
+        self
+    }
+
+    /// 注册中缀运算符，例如`a + b`
+    /// 左结合时右侧的绑定力稍大，于是`1 - 2 - 3`会被解析为`(1 - 2) - 3`，右结合则相反
+    fn infix<P, A, F>(mut self, op: P, assoc: Assoc, bp: u8, build: F) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+        F: Fn(E, E) -> E + 'a,
+    {
+        let bp = u16::from(bp) * 2;
+        let (left_bp, right_bp) = match assoc {
+            Assoc::Left => (bp, bp + 1),
+            Assoc::Right => (bp + 1, bp),
+        };
+        self.infix.push(Infix { op: operator(op), left_bp, right_bp, build: Box::new(build) });
+        self
+    }
+
+    /// 注册后缀运算符，例如`n!`
+    fn postfix<P, A, F>(mut self, op: P, bp: u8, build: F) -> Self
+    where
+        P: Parser<'a, A> + 'a,
+        A: 'a,
+        F: Fn(E) -> E + 'a,
+    {
+        self.postfix.push(Postfix {
+            op: operator(op),
+            left_bp: u16::from(bp) * 2,
+            build: Box::new(build),
+        });
+        self
+    }
+
+    /// 解析一个表达式，其中所有运算符的左绑定力都不小于`min_bp`
+    /// 连续的前缀运算符不递归，先全部读出来，解析完操作数之后从最内层开始依次应用，
+    /// 所以`- - - … 1`再长也不会耗尽栈空间
+    fn expr_bp(&self, input: &'a str, min_bp: u16) -> ParseResult<'a, E> {
+        let mut input = input;
+        let mut prefixes = vec![];
+        while let Some((next_input, prefix)) = self.parse_prefix(input) {
+            input = next_input;
+            prefixes.push(prefix);
+        }
+        let (mut input, mut lhs) = self.atom.parse(input)?;
+
+        loop {
+            // 最内层的前缀运算符的操作数中，运算符的左绑定力不能小于它的右绑定力
+            let min_bp = prefixes.last().map_or(min_bp, |prefix| prefix.right_bp);
+            if let Some((next_input, postfix)) = self.parse_postfix(input) {
+                if postfix.left_bp >= min_bp {
+                    input = next_input;
+                    lhs = (postfix.build)(lhs);
+                    continue;
+                }
+            } else if let Some((next_input, infix)) = self.parse_infix(input) {
+                if infix.left_bp >= min_bp {
+                    let (next_input, rhs) = self.expr_bp(next_input, infix.right_bp)?;
+                    input = next_input;
+                    lhs = (infix.build)(lhs, rhs);
+                    continue;
+                }
+            }
+
+            // 最内层的前缀运算符的操作数到此结束
+            match prefixes.pop() {
+                Some(prefix) => lhs = (prefix.build)(lhs),
+                None => break,
+            }
+        }
+
+        Ok((input, lhs))
+    }
+
+    fn parse_prefix(&self, input: &'a str) -> Option<(&'a str, &Prefix<'a, E>)> {
+        self.prefix.iter().find_map(|p| p.op.parse(input).ok().map(|(next, _)| (next, p)))
+    }
+
+    fn parse_infix(&self, input: &'a str) -> Option<(&'a str, &Infix<'a, E>)> {
+        self.infix.iter().find_map(|p| p.op.parse(input).ok().map(|(next, _)| (next, p)))
+    }
+
+    fn parse_postfix(&self, input: &'a str) -> Option<(&'a str, &Postfix<'a, E>)> {
+        self.postfix.iter().find_map(|p| p.op.parse(input).ok().map(|(next, _)| (next, p)))
+    }
+}
+
+impl<'a, E: 'a> Parser<'a, E> for PrattParser<'a, E> {
+    fn parse(&self, input: &'a str) -> ParseResult<'a, E> {
+        self.expr_bp(input, 0)
+    }
+}
+
+/// 运算符本身的输出没有意义，统一丢弃
+fn operator<'a, P, A>(op: P) -> BoxedParser<'a, ()>
+where
+    P: Parser<'a, A> + 'a,
+    A: 'a,
+{
+    whitespace_wrap(op).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+    use crate::{any_char, either, left, match_literal, one_or_more, right};
+
+    /// 测试用的AST，使用S表达式的形式输出，方便比较结合方式
+    enum Expr {
+        Atom(String),
+        Unary(&'static str, Box<Expr>),
+        Binary(&'static str, Box<Expr>, Box<Expr>),
+    }
+
+    impl fmt::Display for Expr {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Expr::Atom(atom) => write!(f, "{}", atom),
+                Expr::Unary(op, expr) => write!(f, "({} {})", op, expr),
+                Expr::Binary(op, lhs, rhs) => write!(f, "({} {} {})", op, lhs, rhs),
+            }
+        }
+    }
+
+    fn unary(op: &'static str) -> impl Fn(Expr) -> Expr {
+        move |expr| Expr::Unary(op, Box::new(expr))
+    }
+
+    fn binary(op: &'static str) -> impl Fn(Expr, Expr) -> Expr {
+        move |lhs, rhs| Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    /// 数字、标识符或者括号包裹的表达式
+    fn atom<'a>() -> impl Parser<'a, Expr> {
+        let literal = one_or_more(any_char.pred(|c| c.is_ascii_alphanumeric()))
+            .map(|chars| Expr::Atom(chars.into_iter().collect()));
+        let group =
+            right(match_literal("("), left(|input| arithmetic().parse(input), match_literal(")")));
+        either(literal, group)
+    }
+
+    fn arithmetic<'a>() -> PrattParser<'a, Expr> {
+        PrattParser::new(atom())
+            .infix(match_literal("+"), Assoc::Left, 1, binary("+"))
+            .infix(match_literal("-"), Assoc::Left, 1, binary("-"))
+            .infix(match_literal("*"), Assoc::Left, 2, binary("*"))
+            .infix(match_literal("/"), Assoc::Left, 2, binary("/"))
+            .prefix(match_literal("-"), 3, unary("-"))
+            .infix(match_literal("^"), Assoc::Right, 4, binary("^"))
+            .postfix(match_literal("!"), 5, unary("!"))
+    }
+
+    fn boolean<'a>() -> PrattParser<'a, Expr> {
+        PrattParser::new(atom())
+            .infix(match_literal("||"), Assoc::Left, 1, binary("||"))
+            .infix(match_literal("&&"), Assoc::Left, 2, binary("&&"))
+            .infix(match_literal("=="), Assoc::Left, 3, binary("=="))
+            .prefix(match_literal("!"), 4, unary("!"))
+    }
+
+    fn parse<'a>(
+        parser: &PrattParser<'a, Expr>,
+        input: &'a str,
+    ) -> Result<(&'a str, String), &'a str> {
+        parser.parse(input).map(|(rest, expr)| (rest, expr.to_string()))
+    }
+
+    #[test]
+    fn precedence() {
+        let parser = arithmetic();
+        assert_eq!(
+            Ok(("", "(+ 1 (* 2 (- (^ 3 2))))".to_owned())),
+            parse(&parser, "1 + 2 * -3 ^ 2")
+        );
+        assert_eq!(Ok(("", "(+ (* 1 2) 3)".to_owned())), parse(&parser, "1*2+3"));
+        assert_eq!(Ok(("", "(* 1 (+ 2 3))".to_owned())), parse(&parser, "1 * (2 + 3)"));
+    }
+
+    #[test]
+    fn associativity() {
+        let parser = arithmetic();
+        assert_eq!(Ok(("", "(- (- 1 2) 3)".to_owned())), parse(&parser, "1 - 2 - 3"));
+        assert_eq!(Ok(("", "(^ 2 (^ 3 2))".to_owned())), parse(&parser, "2 ^ 3 ^ 2"));
+    }
+
+    #[test]
+    fn prefix_and_postfix() {
+        let parser = arithmetic();
+        assert_eq!(Ok(("", "(- (- 1))".to_owned())), parse(&parser, "--1"));
+        // 后缀运算符比前缀运算符结合得更紧密
+        assert_eq!(Ok(("", "(- (! 3))".to_owned())), parse(&parser, "-3!"));
+        assert_eq!(Ok(("", "(* (! 2) 3)".to_owned())), parse(&parser, "2! * 3"));
+    }
+
+    #[test]
+    fn long_prefix_chain() {
+        // 奇数个`-`，最后的`+ 2`作用于整个前缀表达式
+        let input = format!("{}1 + 2", "- ".repeat(100_001));
+        let digit = any_char.pred(char::is_ascii_digit).map(|c| i64::from(c as u8 - b'0'));
+        let parser = PrattParser::new(digit)
+            .infix(match_literal("+"), Assoc::Left, 1, |lhs, rhs| lhs + rhs)
+            .prefix(match_literal("-"), 3, |value: i64| -value);
+        assert_eq!(Ok(("", 1)), parser.parse(&input));
+    }
+
+    #[test]
+    fn boolean_expression() {
+        let parser = boolean();
+        assert_eq!(
+            Ok(("", "(|| (&& (! a) b) (== c d))".to_owned())),
+            parse(&parser, "!a && b || c == d")
+        );
+    }
+
+    #[test]
+    fn leftover_and_failure() {
+        let parser = arithmetic();
+        // 无法识别的部分原样留给后续的解析器
+        assert_eq!(Ok(("; rest", "(+ 1 2)".to_owned())), parse(&parser, "1 + 2; rest"));
+        // 运算符右侧缺少操作数
+        assert_eq!(Err(""), parse(&parser, "1 +"));
+        assert_eq!(Err(")"), parse(&parser, ")"));
+    }
+}
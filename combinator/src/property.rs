@@ -0,0 +1,398 @@
+//! 基于性质的测试工具
+//! 1. `Rng`：可以复现的伪随机数，同一个种子总是产生同样的用例
+//! 2. `ElementGen`：随机的`Element`树，名称和属性值中混有多字节字符；
+//!    `serialise`在允许出现空白的位置随机插入空白
+//! 3. `assert_round_trip`：对生成的每一个值检查`parse(serialise(x)) == x`
+//! 4. `assert_no_panic`：从一组样本出发随机修改输入，检查解析器不会panic，
+//!    发现panic时把输入逐步缩小到仍然会panic的最短形式再报告
+//!
+//! 失败信息中包含种子和用例的序号，把种子固定下来就可以重现
+
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+use crate::{Element, is_name_char, is_name_start_char};
+
+/// SplitMix64，足够均匀，而且不需要额外的依赖
+#[derive(Clone, Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `0..n`中的一个数，`n`不能为0
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// 以`1/n`的概率返回`true`
+    pub(crate) fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    pub(crate) fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+/// 生成名称和文本时使用的字符，包括多字节字符以及解析器关心的标点
+const CHARS: &[char] = &[
+    'a', 'b', 'z', 'A', 'Z', '_', ':', '-', '.', '0', '9', ' ', '\t', '\n', '<', '>', '/', '=',
+    '"', '\'', '&', 'é', 'ß', 'λ', '中', '文', '·', '\u{300}', '😀',
+];
+
+#[derive(Clone, Debug)]
+pub(crate) struct ElementGen {
+    pub(crate) max_depth: usize,
+    pub(crate) max_children: usize,
+    pub(crate) max_attributes: usize,
+    /// 名称和属性值的最大字符数
+    pub(crate) max_len: usize,
+}
+
+impl Default for ElementGen {
+    fn default() -> Self {
+        Self { max_depth: 4, max_children: 4, max_attributes: 3, max_len: 8 }
+    }
+}
+
+impl ElementGen {
+    /// 符合`element`语法的元素：名称符合XML规范，同一个元素的属性不重名，没有文本
+    pub(crate) fn generate(&self, rng: &mut Rng) -> Element {
+        self.element(rng, 1)
+    }
+
+    fn element(&self, rng: &mut Rng, depth: usize) -> Element {
+        let mut el = Element::new(self.name(rng));
+        for _ in 0..rng.below(self.max_attributes + 1) {
+            let name = self.name(rng);
+            if el.attribute(&name).is_none() {
+                let value = self.string(rng, |c| c != '"');
+                el.attributes.push((name, value));
+            }
+        }
+        if depth < self.max_depth {
+            for _ in 0..rng.below(self.max_children + 1) {
+                el.children.push(self.element(rng, depth + 1));
+            }
+        }
+        el
+    }
+
+    fn name(&self, rng: &mut Rng) -> String {
+        let mut name = String::new();
+        name.push(loop {
+            let c = rng.pick(CHARS);
+            if is_name_start_char(c) {
+                break c;
+            }
+        });
+        name.push_str(&self.string(rng, is_name_char));
+        name
+    }
+
+    /// 最多`max_len`个满足`allowed`的字符
+    fn string(&self, rng: &mut Rng, allowed: impl Fn(char) -> bool) -> String {
+        let len = rng.below(self.max_len + 1);
+        let candidates = CHARS.iter().copied().filter(|c| allowed(*c)).collect::<Vec<_>>();
+        (0..len).map(|_| rng.pick(&candidates)).collect()
+    }
+}
+
+/// 与`Display`相同的XML，但在子元素之间以及属性之间随机插入空白
+pub(crate) fn serialise(el: &Element, rng: &mut Rng) -> String {
+    let mut out = String::new();
+    write_element(&mut out, el, rng);
+    out
+}
+
+fn write_element(out: &mut String, el: &Element, rng: &mut Rng) {
+    out.push('<');
+    out.push_str(&el.name);
+    for (name, value) in &el.attributes {
+        out.push_str(&whitespace(rng, 1));
+        out.push_str(&format!("{}=\"{}\"", name, value));
+    }
+    if el.children.is_empty() && rng.one_in(2) {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for child in &el.children {
+        out.push_str(&whitespace(rng, 0));
+        write_element(out, child, rng);
+    }
+    out.push_str(&whitespace(rng, 0));
+    out.push_str(&format!("</{}>", el.name));
+}
+
+/// 至少`min`个空白字符
+fn whitespace(rng: &mut Rng, min: usize) -> String {
+    (0..min + rng.below(3)).map(|_| rng.pick(&[' ', '\t', '\n', '\r'])).collect()
+}
+
+/// 对`cases`个生成的值检查`parse(serialise(value)) == Some(value)`
+pub(crate) fn assert_round_trip<T, G, S, P>(
+    seed: u64,
+    cases: usize,
+    generate: G,
+    serialise: S,
+    parse: P,
+) where
+    T: PartialEq + Debug,
+    G: Fn(&mut Rng) -> T,
+    S: Fn(&T, &mut Rng) -> String,
+    P: Fn(&str) -> Option<T>,
+{
+    let mut rng = Rng::new(seed);
+    for case in 0..cases {
+        let value = generate(&mut rng);
+        let text = serialise(&value, &mut rng);
+        assert_eq!(
+            Some(&value),
+            parse(&text).as_ref(),
+            "round trip failed for case {} with seed {}, input: {:?}",
+            case,
+            seed,
+            text
+        );
+    }
+}
+
+/// 从`corpus`中的样本出发，对`cases`个随机修改过的输入运行`parse`，任何panic都会导致断言失败
+pub(crate) fn assert_no_panic<F>(seed: u64, cases: usize, corpus: &[&str], parse: F)
+where
+    F: Fn(&str),
+{
+    if let Some((case, input)) = find_panic(seed, cases, corpus, &parse) {
+        panic!("parser panicked on case {} with seed {}, shrunk input: {:?}", case, seed, input);
+    }
+}
+
+/// 返回第一个导致panic的用例序号以及缩小之后的输入
+pub(crate) fn find_panic<F>(
+    seed: u64,
+    cases: usize,
+    corpus: &[&str],
+    parse: F,
+) -> Option<(usize, String)>
+where
+    F: Fn(&str),
+{
+    let panics =
+        |input: &str| silently(|| panic::catch_unwind(AssertUnwindSafe(|| parse(input)))).is_err();
+    let mut rng = Rng::new(seed);
+    for case in 0..cases {
+        let mut input = rng.pick(corpus).to_owned();
+        for _ in 0..1 + rng.below(4) {
+            input = mutate(&input, &mut rng);
+        }
+        if panics(&input) {
+            return Some((case, shrink(input, panics)));
+        }
+    }
+    None
+}
+
+/// 随机插入、删除、替换、复制或截断一段字符，结果总是合法的UTF-8
+pub(crate) fn mutate(input: &str, rng: &mut Rng) -> String {
+    let chars = input.chars().collect::<Vec<_>>();
+    let at = rng.below(chars.len() + 1);
+    let end = at + rng.below(chars.len() - at + 1);
+    let mut result = chars[..at].to_vec();
+    match rng.below(5) {
+        0 => {
+            result.push(rng.pick(CHARS));
+            result.extend(&chars[at..]);
+        }
+        1 => result.extend(&chars[end..]),
+        2 => {
+            result.push(rng.pick(CHARS));
+            result.extend(chars.get(at + 1..).unwrap_or_default());
+        }
+        3 => {
+            result.extend(&chars[at..end]);
+            result.extend(&chars[at..]);
+        }
+        _ => {}
+    }
+    result.into_iter().collect()
+}
+
+thread_local! {
+    static SILENCED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 运行`f`期间当前线程的panic不输出信息，缩小输入时会反复触发同一个panic
+/// 全局的钩子只安装一次，其他线程以及`f`之外的panic仍然交给原来的钩子
+fn silently<T>(f: impl FnOnce() -> T) -> T {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !SILENCED.with(Cell::get) {
+                previous(info);
+            }
+        }));
+    });
+
+    let outer = SILENCED.with(|silenced| silenced.replace(true));
+    let result = f();
+    SILENCED.with(|silenced| silenced.set(outer));
+    result
+}
+
+/// 不断尝试删除一段字符，只要删除后仍然panic就保留这次删除，直到无法再缩小
+/// 删除的长度从一半开始逐步减半，最后逐个字符地尝试
+pub(crate) fn shrink(input: String, fails: impl Fn(&str) -> bool) -> String {
+    let mut chars = input.chars().collect::<Vec<_>>();
+    let mut size = chars.len() / 2;
+    while size > 0 {
+        let mut start = 0;
+        let mut removed = false;
+        while start + size <= chars.len() {
+            let candidate = chars[..start].iter().chain(&chars[start + size..]).collect::<String>();
+            if fails(&candidate) {
+                chars.drain(start..start + size);
+                removed = true;
+            } else {
+                start += size;
+            }
+        }
+        if !removed {
+            size /= 2;
+        }
+    }
+    chars.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Parser, element,
+        html::parse_html,
+        json::parse_json,
+        match_literal, quoted_string,
+        toml::parse_toml,
+        xml::{Limits, parse_xml},
+        yaml::parse_yaml,
+    };
+
+    const XML_CORPUS: &[&str] = &[
+        r#"<top label="Top"><semi-bottom label="Bottom"/><middle><bottom/></middle></top>"#,
+        "<子元素 属性=\"中文😀\">\n  <a/>\n</子元素>",
+        "<a b=\"\"/>",
+    ];
+
+    #[test]
+    fn element_round_trip() {
+        let generator = ElementGen::default();
+        assert_round_trip(
+            1,
+            300,
+            |rng| generator.generate(rng),
+            serialise,
+            |input| match element().parse(input) {
+                Ok(("", el)) => Some(el),
+                _ => None,
+            },
+        );
+        // `Display`输出的紧凑形式同样可以解析回来
+        assert_round_trip(
+            2,
+            300,
+            |rng| generator.generate(rng),
+            |el, _| el.to_string(),
+            |input| parse_xml(input, &Limits::default()).ok(),
+        );
+    }
+
+    /// 种子、样本以及调用解析器的方式
+    type Case = (u64, &'static [&'static str], fn(&str));
+
+    #[test]
+    fn parsers_never_panic() {
+        let cases: &[Case] = &[
+            (3, XML_CORPUS, |input| {
+                let _ = element().parse(input);
+            }),
+            // 很小的限制让变异后的输入也能走到超出限制的路径
+            (4, XML_CORPUS, |input| {
+                let limits = Limits::default().max_depth(2).max_attributes(1).max_name_len(4);
+                let _ = parse_xml(input, &limits);
+            }),
+            // `match_literal`按字节长度截取输入，截取位置可能落在多字节字符的中间
+            (5, &["中文", "😀a", "é"], |input| {
+                let _ = match_literal("ab").parse(input);
+                let _ = match_literal("中").parse(input);
+                let _ = quoted_string().parse(input);
+            }),
+            (
+                6,
+                &[r#"{"a": [1, -2.5e3, "é😀"], "b": [[{}]]}"#, "[[[[[[[[null]]]]]]]]"],
+                |input| {
+                    let _ = parse_json(input);
+                },
+            ),
+            (7, &["name: 中文\nitems:\n  - a\n  - b: c\n", "- 😀\n-\n  x: y\n"], |input| {
+                let _ = parse_yaml(input);
+            }),
+            (
+                8,
+                &["[服务]\nname = \"中文😀\"\nports = [80, 443]\nratio = -1.5e3", "a = true"],
+                |input| {
+                    let _ = parse_toml(input);
+                },
+            ),
+            (
+                9,
+                &["<div class=x hidden><p>中文 &amp; 😀<br></p></div>", "<a href='é'>&lt;</a>"],
+                |input| {
+                    let _ = parse_html(input);
+                },
+            ),
+        ];
+        for &(seed, corpus, parse) in cases {
+            assert_no_panic(seed, 2000, corpus, parse);
+        }
+    }
+
+    #[test]
+    fn shrinks_to_minimal_input() {
+        let found = find_panic(7, 1000, XML_CORPUS, |input| {
+            if input.contains("</") && input.contains('😀') {
+                panic!("boom");
+            }
+        });
+
+        let (_, input) = found.unwrap();
+        // 只剩下触发panic所必需的字符
+        assert_eq!(3, input.chars().count());
+        assert!(input.contains("</") && input.contains('😀'));
+    }
+
+    #[test]
+    fn mutations_stay_close_to_corpus() {
+        let mut rng = Rng::new(8);
+        for _ in 0..1000 {
+            let input = XML_CORPUS[rng.below(XML_CORPUS.len())];
+            let mutated = mutate(input, &mut rng);
+            assert!(mutated.chars().count() <= input.chars().count() * 2 + 1);
+        }
+    }
+}
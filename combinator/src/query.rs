@@ -0,0 +1,318 @@
+//! `Element`的查询接口以及简化的XPath选择器
+//! 选择器支持的语法：
+//! 1. `a/b`：`a`的子元素`b`，`a//b`：`a`的所有后代元素`b`
+//! 2. `*`匹配任意名称
+//! 3. `[@name]`要求属性存在，`[@name='value']`要求属性等于给定值，单双引号均可
+//! 4. `[n]`取同一个父元素下第n个匹配的元素，从1开始
+//!
+//! 选择器从一个虚拟的文档节点开始求值，调用`select`的元素是它唯一的子元素，
+//! 所以`top/middle`与`/top/middle`相同，`//bottom`会匹配所有的`bottom`，包括根元素本身
+
+use std::{
+    collections::{HashSet, VecDeque},
+    ptr,
+};
+
+use crate::{
+    Element, Parser, any_char, either, end_of_input, identifier, left, match_literal, one_or_more,
+    optional, pair, right, whitespace_wrap, zero_or_more,
+};
+
+impl Element {
+    /// 属性的值，属性不存在时返回`None`
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// 第一个名为`name`的子元素
+    pub(crate) fn child_named(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub(crate) fn children_named<'e>(&'e self, name: &'e str) -> impl Iterator<Item = &'e Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// 按文档顺序（深度优先、先序）遍历所有后代元素，不包括自身
+    pub(crate) fn descendants(&self) -> Descendants<'_> {
+        Descendants { stack: self.children.iter().rev().collect() }
+    }
+
+    /// 按层次（广度优先）遍历所有后代元素，不包括自身
+    pub(crate) fn descendants_breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst { queue: self.children.iter().collect() }
+    }
+
+    /// 返回所有匹配选择器的元素，按文档顺序排列，选择器的语法错误返回剩余的输入
+    pub(crate) fn select<'s>(&self, selector: &'s str) -> Result<Vec<&Element>, &'s str> {
+        let steps = match left(path(), end_of_input()).parse(selector) {
+            Ok((_, steps)) => steps,
+            Err(err) => return Err(err),
+        };
+
+        // 第一步的上下文是虚拟的文档节点，它只有一个子元素
+        let mut steps = steps.iter();
+        let first = steps.next().expect("a path has at least one step");
+        let mut groups = vec![vec![self]];
+        if first.axis == Axis::Descendant {
+            groups.extend(
+                std::iter::once(self)
+                    .chain(self.descendants())
+                    .map(|el| el.children.iter().collect()),
+            );
+        }
+        let mut context = first.select(groups);
+
+        for step in steps {
+            let groups = match step.axis {
+                Axis::Child => context.iter().map(|el| el.children.iter().collect()).collect(),
+                Axis::Descendant => {
+                    // `//`可能从不同的祖先元素到达同一个父元素，每个父元素只计算一次
+                    let mut parents = HashSet::new();
+                    context
+                        .iter()
+                        .flat_map(|el| std::iter::once(*el).chain(el.descendants()))
+                        .filter(|el| parents.insert(ptr::from_ref(*el)))
+                        .map(|el| el.children.iter().collect())
+                        .collect()
+                }
+            };
+            context = step.select(groups);
+        }
+
+        // 嵌套的上下文元素会打乱顺序，例如`//*/*`，最后统一按文档顺序排列
+        let selected = context.into_iter().map(ptr::from_ref).collect::<HashSet<_>>();
+        Ok(std::iter::once(self)
+            .chain(self.descendants())
+            .filter(|el| selected.contains(&ptr::from_ref(*el)))
+            .collect())
+    }
+}
+
+pub(crate) struct Descendants<'e> {
+    stack: Vec<&'e Element>,
+}
+
+impl<'e> Iterator for Descendants<'e> {
+    type Item = &'e Element;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let el = self.stack.pop()?;
+        self.stack.extend(el.children.iter().rev());
+        Some(el)
+    }
+}
+
+pub(crate) struct BreadthFirst<'e> {
+    queue: VecDeque<&'e Element>,
+}
+
+impl<'e> Iterator for BreadthFirst<'e> {
+    type Item = &'e Element;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let el = self.queue.pop_front()?;
+        self.queue.extend(el.children.iter());
+        Some(el)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Axis {
+    Child,
+    Descendant,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Step {
+    axis: Axis,
+    /// `None`表示`*`
+    name: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Predicate {
+    HasAttribute(String),
+    AttributeEquals(String, String),
+    Position(usize),
+}
+
+impl Step {
+    /// `groups`中的每一组是同一个父元素的子元素，`[n]`在每一组中分别计数，结果中没有重复的元素
+    fn select<'e>(&self, groups: Vec<Vec<&'e Element>>) -> Vec<&'e Element> {
+        let mut seen = HashSet::new();
+        groups
+            .into_iter()
+            .flat_map(|candidates| self.filter(candidates))
+            .filter(|el| seen.insert(ptr::from_ref(*el)))
+            .collect()
+    }
+
+    /// 从同一个父元素的子元素中筛选，`[n]`按筛选到这一步时的顺序计数
+    fn filter<'e>(&self, candidates: Vec<&'e Element>) -> Vec<&'e Element> {
+        let mut matches: Vec<&Element> = candidates
+            .into_iter()
+            .filter(|el| self.name.as_ref().is_none_or(|name| el.name == *name))
+            .collect();
+
+        for predicate in &self.predicates {
+            matches = match predicate {
+                Predicate::HasAttribute(name) => {
+                    matches.into_iter().filter(|el| el.attribute(name).is_some()).collect()
+                }
+                Predicate::AttributeEquals(name, value) => matches
+                    .into_iter()
+                    .filter(|el| el.attribute(name) == Some(value.as_str()))
+                    .collect(),
+                Predicate::Position(n) => matches.get(n - 1).copied().into_iter().collect(),
+            };
+        }
+        matches
+    }
+}
+
+/// path = ["/" | "//"] step *(("/" | "//") step)
+fn path<'a>() -> impl Parser<'a, Vec<Step>> {
+    let axis = || {
+        either(
+            match_literal("//").map(|_| Axis::Descendant),
+            match_literal("/").map(|_| Axis::Child),
+        )
+    };
+
+    pair(optional(axis()), pair(step(), zero_or_more(pair(axis(), step())))).map(
+        |(first_axis, ((name, predicates), rest))| {
+            let mut steps =
+                vec![Step { axis: first_axis.unwrap_or(Axis::Child), name, predicates }];
+            for (axis, (name, predicates)) in rest {
+                steps.push(Step { axis, name, predicates });
+            }
+            steps
+        },
+    )
+}
+
+fn step<'a>() -> impl Parser<'a, (Option<String>, Vec<Predicate>)> {
+    let name_test = either(identifier.map(Some), match_literal("*").map(|_| None));
+    pair(name_test, zero_or_more(predicate()))
+}
+
+fn predicate<'a>() -> impl Parser<'a, Predicate> {
+    let attribute = right(
+        match_literal("@"),
+        pair(identifier, optional(right(whitespace_wrap(match_literal("=")), literal()))),
+    )
+    .map(|(name, value)| match value {
+        Some(value) => Predicate::AttributeEquals(name, value),
+        None => Predicate::HasAttribute(name),
+    });
+    let position = one_or_more(any_char.pred(char::is_ascii_digit))
+        .map(|digits| digits.into_iter().collect::<String>().parse())
+        .pred(|n| matches!(n, Ok(1..)))
+        .map(|n| Predicate::Position(n.unwrap()));
+
+    right(
+        match_literal("["),
+        left(whitespace_wrap(either(attribute, position)), match_literal("]")),
+    )
+}
+
+/// 单引号或双引号包裹的字符串
+fn literal<'a>() -> impl Parser<'a, String> {
+    let quoted = |quote: char| {
+        right(
+            any_char.pred(move |c| *c == quote),
+            left(
+                zero_or_more(any_char.pred(move |c| *c != quote)),
+                any_char.pred(move |c| *c == quote),
+            ),
+        )
+    };
+    either(quoted('"'), quoted('\'')).map(|chars| chars.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element;
+
+    fn doc() -> Element {
+        let doc = r#"
+            <top label="Top">
+                <semi-bottom label="Bottom"/>
+                <middle>
+                    <bottom label="x"/>
+                    <bottom label="y">
+                        <bottom label="z"/>
+                    </bottom>
+                </middle>
+                <middle>
+                    <bottom label="x" kind="leaf"/>
+                </middle>
+            </top>"#;
+        element().parse(doc).unwrap().1
+    }
+
+    fn labels(elements: Vec<&Element>) -> Vec<&str> {
+        elements.into_iter().map(|el| el.attribute("label").unwrap_or(&el.name)).collect()
+    }
+
+    #[test]
+    fn lookup() {
+        let doc = doc();
+        assert_eq!(Some("Top"), doc.attribute("label"));
+        assert_eq!(None, doc.attribute("missing"));
+        assert_eq!(
+            Some("Bottom"),
+            doc.child_named("semi-bottom").and_then(|el| el.attribute("label"))
+        );
+        assert!(doc.child_named("bottom").is_none());
+        assert_eq!(2, doc.children_named("middle").count());
+    }
+
+    #[test]
+    fn traversal_order() {
+        let doc = doc();
+        assert_eq!(
+            vec!["Bottom", "middle", "x", "y", "z", "middle", "x"],
+            labels(doc.descendants().collect())
+        );
+        assert_eq!(
+            vec!["Bottom", "middle", "middle", "x", "y", "x", "z"],
+            labels(doc.descendants_breadth_first().collect())
+        );
+    }
+
+    #[test]
+    fn selectors() {
+        let doc = doc();
+        assert_eq!(vec!["x", "y", "x"], labels(doc.select("top/middle/bottom").unwrap()));
+        assert_eq!(vec!["x", "x"], labels(doc.select("top/middle/bottom[@label='x']").unwrap()));
+        assert_eq!(vec!["x", "y", "z", "x"], labels(doc.select("//bottom").unwrap()));
+        assert_eq!(vec!["z"], labels(doc.select("/top//bottom/bottom").unwrap()));
+        assert_eq!(vec!["x"], labels(doc.select(r#"//bottom[@kind][@label="x"]"#).unwrap()));
+        assert_eq!(vec!["Top"], labels(doc.select("//top").unwrap()));
+        assert_eq!(vec!["Bottom", "middle", "middle"], labels(doc.select("top/*").unwrap()));
+        // 位置在每个父元素下分别计数
+        assert_eq!(vec!["x", "x"], labels(doc.select("top/middle/bottom[1]").unwrap()));
+        assert_eq!(vec!["x"], labels(doc.select("top/middle[2]/bottom").unwrap()));
+        assert_eq!(vec!["x", "z", "x"], labels(doc.select("//bottom[1]").unwrap()));
+        assert_eq!(vec!["y"], labels(doc.select("top//bottom[2]").unwrap()));
+        assert_eq!(vec!["z"], labels(doc.select("//middle//bottom[@label='z'][1]").unwrap()));
+        assert!(doc.select("middle").unwrap().is_empty());
+        assert_eq!(
+            vec!["Bottom", "middle", "x", "y", "z", "middle", "x"],
+            labels(doc.select("//*/*").unwrap())
+        );
+    }
+
+    #[test]
+    fn invalid_selectors() {
+        let doc = doc();
+        assert_eq!(Err("[@label=x]"), doc.select("top[@label=x]"));
+        assert_eq!(Err("[0]"), doc.select("top[0]"));
+        assert_eq!(Err("/"), doc.select("top/"));
+        assert_eq!(Err(""), doc.select(""));
+    }
+}
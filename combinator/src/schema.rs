@@ -0,0 +1,453 @@
+//! `Element`树的模式校验
+//! 模式声明了每个元素允许的子元素及其数量、必需或可选的属性以及属性值的类型，
+//! 既可以在Rust中通过构建器编写，也可以从一个简化的XML模式文件中加载：
+//!
+//! ```xml
+//! <schema root="top">
+//!     <element name="top">
+//!         <attribute name="label" type="string" use="required"/>
+//!         <child name="middle" min="0" max="unbounded"/>
+//!     </element>
+//!     <element name="middle"/>
+//! </schema>
+//! ```
+//!
+//! 子元素只检查数量，不检查先后顺序，文档中出现的每个元素都必须在模式中声明
+
+use std::fmt;
+
+use crate::{
+    Element,
+    xml::{Limits, XmlError, parse_xml},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Schema {
+    root: String,
+    elements: Vec<ElementRule>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ElementRule {
+    name: String,
+    attributes: Vec<AttributeRule>,
+    children: Vec<ChildRule>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct AttributeRule {
+    name: String,
+    required: bool,
+    value_type: ValueType,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ValueType {
+    String,
+    Integer,
+    Boolean,
+    /// 只能是列出的值之一
+    Enum(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ChildRule {
+    name: String,
+    cardinality: Cardinality,
+}
+
+/// 子元素出现次数的范围，`max`为`None`表示没有上限
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cardinality {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Cardinality {
+    const ONE: Cardinality = Cardinality { min: 1, max: Some(1) };
+    const OPTIONAL: Cardinality = Cardinality { min: 0, max: Some(1) };
+    const ZERO_OR_MORE: Cardinality = Cardinality { min: 0, max: None };
+    const ONE_OR_MORE: Cardinality = Cardinality { min: 1, max: None };
+}
+
+impl Schema {
+    fn new(root: &str) -> Self {
+        Self { root: root.to_owned(), elements: vec![] }
+    }
+
+    fn element(mut self, rule: ElementRule) -> Self {
+        self.elements.push(rule);
+        self
+    }
+
+    fn rule(&self, name: &str) -> Option<&ElementRule> {
+        self.elements.iter().find(|rule| rule.name == name)
+    }
+}
+
+impl ElementRule {
+    fn new(name: &str) -> Self {
+        Self { name: name.to_owned(), attributes: vec![], children: vec![] }
+    }
+
+    fn required(mut self, name: &str, value_type: ValueType) -> Self {
+        self.attributes.push(AttributeRule { name: name.to_owned(), required: true, value_type });
+        self
+    }
+
+    fn optional(mut self, name: &str, value_type: ValueType) -> Self {
+        self.attributes.push(AttributeRule { name: name.to_owned(), required: false, value_type });
+        self
+    }
+
+    fn child(mut self, name: &str, cardinality: Cardinality) -> Self {
+        self.children.push(ChildRule { name: name.to_owned(), cardinality });
+        self
+    }
+}
+
+impl ValueType {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ValueType::String => true,
+            ValueType::Integer => value.parse::<i64>().is_ok(),
+            ValueType::Boolean => value == "true" || value == "false",
+            ValueType::Enum(values) => values.iter().any(|v| v == value),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::String => f.write_str("string"),
+            ValueType::Integer => f.write_str("integer"),
+            ValueType::Boolean => f.write_str("boolean"),
+            ValueType::Enum(values) => write!(f, "one of {}", values.join(", ")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Violation {
+    /// 出错元素的路径，例如`/top/middle[1]/bottom[2]`，下标从1开始，只在同名的兄弟元素之间计数
+    path: String,
+    kind: ViolationKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ViolationKind {
+    UnexpectedRoot { expected: String },
+    UndeclaredElement,
+    UnexpectedChild(String),
+    TooFewChildren { name: String, min: usize, found: usize },
+    TooManyChildren { name: String, max: usize, found: usize },
+    MissingAttribute(String),
+    UnexpectedAttribute(String),
+    InvalidAttributeValue { name: String, expected: ValueType, value: String },
+}
+
+impl Schema {
+    /// 校验整棵树，返回所有违反模式的地方，而不是在第一个错误处停下
+    fn validate(&self, root: &Element) -> Result<(), Vec<Violation>> {
+        let mut violations = vec![];
+        let path = format!("/{}", root.name);
+
+        if root.name != self.root {
+            violations.push(Violation {
+                path: path.clone(),
+                kind: ViolationKind::UnexpectedRoot { expected: self.root.clone() },
+            });
+        }
+        self.validate_element(root, &path, &mut violations);
+
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+
+    fn validate_element(&self, el: &Element, path: &str, violations: &mut Vec<Violation>) {
+        let mut report = |kind| violations.push(Violation { path: path.to_owned(), kind });
+
+        let rule = match self.rule(&el.name) {
+            Some(rule) => rule,
+            None => return report(ViolationKind::UndeclaredElement),
+        };
+
+        for attribute in &rule.attributes {
+            match el.attributes.iter().find(|(name, _)| *name == attribute.name) {
+                Some((_, value)) if !attribute.value_type.accepts(value) => {
+                    report(ViolationKind::InvalidAttributeValue {
+                        name: attribute.name.clone(),
+                        expected: attribute.value_type.clone(),
+                        value: value.clone(),
+                    })
+                }
+                None if attribute.required => {
+                    report(ViolationKind::MissingAttribute(attribute.name.clone()))
+                }
+                _ => {}
+            }
+        }
+        for (name, _) in &el.attributes {
+            if !rule.attributes.iter().any(|attribute| attribute.name == *name) {
+                report(ViolationKind::UnexpectedAttribute(name.clone()));
+            }
+        }
+
+        for child in &rule.children {
+            let found = el.children.iter().filter(|c| c.name == child.name).count();
+            let Cardinality { min, max } = child.cardinality;
+            if found < min {
+                report(ViolationKind::TooFewChildren { name: child.name.clone(), min, found });
+            }
+            if let Some(max) = max.filter(|max| found > *max) {
+                report(ViolationKind::TooManyChildren { name: child.name.clone(), max, found });
+            }
+        }
+        for child in &el.children {
+            if !rule.children.iter().any(|c| c.name == child.name) {
+                report(ViolationKind::UnexpectedChild(child.name.clone()));
+            }
+        }
+
+        for (i, child) in el.children.iter().enumerate() {
+            let index = el.children[..i].iter().filter(|c| c.name == child.name).count() + 1;
+            let child_path = format!("{}/{}[{}]", path, child.name, index);
+            // 不允许出现的子元素已经报告过了，不再深入检查
+            if rule.children.iter().any(|c| c.name == child.name) {
+                self.validate_element(child, &child_path, violations);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SchemaError {
+    Xml(XmlError),
+    /// 模式文件的结构不正确，`path`为出错元素的路径
+    Invalid {
+        path: String,
+        message: String,
+    },
+}
+
+impl Schema {
+    /// 从简化的XML模式文件中加载
+    fn from_xml(input: &str) -> Result<Self, SchemaError> {
+        // 模式文件只描述元素的结构，不需要很深的嵌套或者很长的文本
+        let limits = Limits::default().max_depth(32).max_input_len(1024 * 1024);
+        let doc = parse_xml(input, &limits).map_err(SchemaError::Xml)?;
+        let invalid = |path: &str, message: &str| SchemaError::Invalid {
+            path: path.to_owned(),
+            message: message.to_owned(),
+        };
+
+        if doc.name != "schema" {
+            return Err(invalid("/", "root element must be <schema>"));
+        }
+        let root = doc.attribute("root").ok_or_else(|| invalid("/schema", "missing `root`"))?;
+        let mut schema = Schema::new(root);
+
+        for (i, decl) in doc.children.iter().enumerate() {
+            let path = format!("/schema/{}[{}]", decl.name, i + 1);
+            if decl.name != "element" {
+                return Err(invalid(&path, "expected <element>"));
+            }
+            let name = decl.attribute("name").ok_or_else(|| invalid(&path, "missing `name`"))?;
+            let mut rule = ElementRule::new(name);
+
+            for item in &decl.children {
+                let path = format!("{}/{}", path, item.name);
+                let name =
+                    item.attribute("name").ok_or_else(|| invalid(&path, "missing `name`"))?;
+                match item.name.as_str() {
+                    "attribute" => {
+                        let value_type = match item.attribute("type").unwrap_or("string") {
+                            "string" => ValueType::String,
+                            "integer" => ValueType::Integer,
+                            "boolean" => ValueType::Boolean,
+                            "enum" => ValueType::Enum(
+                                item.attribute("values")
+                                    .ok_or_else(|| invalid(&path, "missing `values`"))?
+                                    .split_whitespace()
+                                    .map(str::to_owned)
+                                    .collect(),
+                            ),
+                            _ => return Err(invalid(&path, "unknown `type`")),
+                        };
+                        rule = match item.attribute("use").unwrap_or("optional") {
+                            "required" => rule.required(name, value_type),
+                            "optional" => rule.optional(name, value_type),
+                            _ => return Err(invalid(&path, "`use` must be required or optional")),
+                        };
+                    }
+                    "child" => {
+                        let count = |key, default| match item.attribute(key) {
+                            None => Ok(Some(default)),
+                            Some("unbounded") => Ok(None),
+                            Some(value) => value
+                                .parse()
+                                .map(Some)
+                                .map_err(|_| invalid(&path, "`min`/`max` must be a number")),
+                        };
+                        let min = count("min", 1)?
+                            .ok_or_else(|| invalid(&path, "`min` cannot be unbounded"))?;
+                        let max = count("max", 1)?;
+                        rule = rule.child(name, Cardinality { min, max });
+                    }
+                    _ => return Err(invalid(&path, "expected <attribute> or <child>")),
+                }
+            }
+            schema = schema.element(rule);
+        }
+
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, element};
+
+    /// 与`xml_parser`测试中的文档相对应的模式
+    fn top_schema() -> Schema {
+        Schema::new("top")
+            .element(
+                ElementRule::new("top")
+                    .required("label", ValueType::String)
+                    .child("semi-bottom", Cardinality::OPTIONAL)
+                    .child("middle", Cardinality::ZERO_OR_MORE),
+            )
+            .element(ElementRule::new("semi-bottom").required("label", ValueType::String))
+            .element(
+                ElementRule::new("middle")
+                    .optional("level", ValueType::Integer)
+                    .child("bottom", Cardinality::ONE_OR_MORE),
+            )
+            .element(
+                ElementRule::new("bottom")
+                    .required("label", ValueType::String)
+                    .optional("kind", ValueType::Enum(vec!["leaf".to_owned(), "stub".to_owned()])),
+            )
+    }
+
+    fn parse(doc: &str) -> Element {
+        element().parse(doc).unwrap().1
+    }
+
+    #[test]
+    fn valid_document() {
+        let doc = parse(
+            r#"
+            <top label="Top">
+                <semi-bottom label="Bottom"/>
+                <middle>
+                    <bottom label="Another bottom"/>
+                </middle>
+            </top>"#,
+        );
+        assert_eq!(Ok(()), top_schema().validate(&doc));
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let doc = parse(
+            r#"
+            <top>
+                <semi-bottom label="1"/>
+                <semi-bottom label="2"/>
+                <middle level="high">
+                    <bottom label="a" kind="tree"/>
+                    <bottom/>
+                </middle>
+                <middle/>
+                <footer/>
+            </top>"#,
+        );
+        let violation = |path: &str, kind| Violation { path: path.to_owned(), kind };
+        assert_eq!(
+            Err(vec![
+                violation("/top", ViolationKind::MissingAttribute("label".to_owned())),
+                violation(
+                    "/top",
+                    ViolationKind::TooManyChildren {
+                        name: "semi-bottom".to_owned(),
+                        max: 1,
+                        found: 2
+                    }
+                ),
+                violation("/top", ViolationKind::UnexpectedChild("footer".to_owned())),
+                violation(
+                    "/top/middle[1]",
+                    ViolationKind::InvalidAttributeValue {
+                        name: "level".to_owned(),
+                        expected: ValueType::Integer,
+                        value: "high".to_owned()
+                    }
+                ),
+                violation(
+                    "/top/middle[1]/bottom[1]",
+                    ViolationKind::InvalidAttributeValue {
+                        name: "kind".to_owned(),
+                        expected: ValueType::Enum(vec!["leaf".to_owned(), "stub".to_owned()]),
+                        value: "tree".to_owned()
+                    }
+                ),
+                violation(
+                    "/top/middle[1]/bottom[2]",
+                    ViolationKind::MissingAttribute("label".to_owned())
+                ),
+                violation(
+                    "/top/middle[2]",
+                    ViolationKind::TooFewChildren { name: "bottom".to_owned(), min: 1, found: 0 }
+                ),
+            ]),
+            top_schema().validate(&doc)
+        );
+    }
+
+    #[test]
+    fn undeclared_elements() {
+        let schema =
+            Schema::new("top").element(ElementRule::new("top").child("other", Cardinality::ONE));
+        let doc = parse(r#"<bottom extra="1"/>"#);
+        assert_eq!(
+            Err(vec![
+                Violation {
+                    path: "/bottom".to_owned(),
+                    kind: ViolationKind::UnexpectedRoot { expected: "top".to_owned() }
+                },
+                Violation { path: "/bottom".to_owned(), kind: ViolationKind::UndeclaredElement },
+            ]),
+            schema.validate(&doc)
+        );
+        let doc = parse(r#"<top><other/></top>"#);
+        assert_eq!(
+            Err(vec![Violation {
+                path: "/top/other[1]".to_owned(),
+                kind: ViolationKind::UndeclaredElement
+            }]),
+            schema.validate(&doc)
+        );
+    }
+
+    #[test]
+    fn load_from_xml() {
+        let schema = Schema::from_xml(include_str!("../fixtures/schema/top.xml"));
+        assert_eq!(Ok(top_schema()), schema);
+    }
+
+    #[test]
+    fn invalid_schema_file() {
+        assert_eq!(
+            Err(SchemaError::Invalid {
+                path: "/schema/element[1]/child".to_owned(),
+                message: "`min`/`max` must be a number".to_owned()
+            }),
+            Schema::from_xml(
+                r#"<schema root="a"><element name="a"><child name="b" max="many"/></element></schema>"#
+            )
+        );
+        assert!(matches!(Schema::from_xml("<schema"), Err(SchemaError::Xml(_))));
+    }
+}
@@ -0,0 +1,1049 @@
+//! `Element`与Rust类型之间的serde映射
+//! 映射规则：
+//! 1. 属性和子元素都按名称对应到结构体的字段，同名的子元素可以反序列化为`Vec`，
+//!    属性与子元素（或者文本字段）同名时无法确定对应关系，反序列化会返回错误
+//! 2. 布尔值、数字、字符串以及单元变体序列化为属性，结构体、序列和其他枚举变体序列化为子元素
+//! 3. 元素的文本对应名为`$text`的字段，可以通过`text_field`修改这个名称
+//! 4. 枚举的变体由子元素的名称决定，例如`<shape><circle radius="1"/></shape>`，
+//!    单元变体也可以写成属性值或者文本，例如`kind="leaf"`，
+//!    元组变体的每一项按顺序对应变体元素的一个子元素，序列化时这些子元素与变体同名
+//!
+//! 没有出现的`Vec`字段需要加上`#[serde(default)]`，属性值中以空白分隔的列表可以反序列化为序列
+
+use std::fmt;
+
+use serde::{
+    de::{self, DeserializeOwned, IntoDeserializer, Visitor},
+    forward_to_deserialize_any, ser,
+    ser::Serialize,
+};
+
+use crate::Element;
+
+const TEXT_FIELD: &str = "$text";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// 把元素反序列化为`T`，根元素的名称会被忽略
+fn from_element<T: DeserializeOwned>(el: &Element) -> Result<T, Error> {
+    T::deserialize(ElementDeserializer::new(el))
+}
+
+/// 把`value`序列化为名为`name`的元素
+fn to_element<T: Serialize + ?Sized>(name: &str, value: &T) -> Result<Element, Error> {
+    ElementSerializer::new(name).serialize(value)
+}
+
+#[derive(Clone, Copy)]
+struct ElementDeserializer<'e> {
+    el: &'e Element,
+    text_field: &'e str,
+}
+
+impl<'e> ElementDeserializer<'e> {
+    fn new(el: &'e Element) -> Self {
+        Self { el, text_field: TEXT_FIELD }
+    }
+
+    /// 元素文本对应的字段名，默认为`$text`
+    fn text_field(mut self, text_field: &'e str) -> Self {
+        self.text_field = text_field;
+        self
+    }
+
+    fn text(&self) -> TextDeserializer<'e> {
+        TextDeserializer(&self.el.text)
+    }
+}
+
+macro_rules! forward_to_text {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            self.text().$method(visitor)
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for ElementDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.el.attributes.is_empty() && self.el.children.is_empty() {
+            visitor.visit_borrowed_str(&self.el.text)
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+
+    forward_to_text! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_identifier
+        deserialize_bytes deserialize_byte_buf
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// 子元素按顺序组成序列
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text_field = self.text_field;
+        let children = self.el.children.iter().map(|el| ElementDeserializer { el, text_field });
+        visitor.visit_seq(de::value::SeqDeserializer::new(children))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(ElementMap::new(self)?)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    /// 唯一的子元素的名称是变体名，没有子元素时文本是单元变体的名称
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.el.children.as_slice() {
+            [] => visitor.visit_enum(ElementEnum { variant: self.el.text.trim(), content: None }),
+            [child] => visitor.visit_enum(ElementEnum {
+                variant: &child.name,
+                content: Some(ElementDeserializer { el: child, text_field: self.text_field }),
+            }),
+            _ => Err(Error(format!(
+                "<{}> must contain exactly one element naming a variant of `{}`",
+                self.el.name, name
+            ))),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for ElementDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// 依次访问属性、按名称分组的子元素以及文本
+struct ElementMap<'e> {
+    entries: std::vec::IntoIter<(&'e str, Entry<'e>)>,
+    value: Option<Entry<'e>>,
+    text_field: &'e str,
+}
+
+enum Entry<'e> {
+    Text(&'e str),
+    Children(Vec<&'e Element>),
+}
+
+impl<'e> ElementMap<'e> {
+    /// 属性、子元素和文本共用同一组键，名称相同时无法确定字段对应哪一个，返回错误
+    fn new(de: ElementDeserializer<'e>) -> Result<Self, Error> {
+        let mut entries: Vec<(&str, Entry)> = de
+            .el
+            .attributes
+            .iter()
+            .map(|(name, value)| (name.as_str(), Entry::Text(value)))
+            .collect();
+        for child in &de.el.children {
+            match entries.iter_mut().find(|(name, _)| *name == child.name) {
+                Some((_, Entry::Children(children))) => children.push(child),
+                Some((_, Entry::Text(_))) => return Err(ambiguous(de.el, &child.name)),
+                None => entries.push((&child.name, Entry::Children(vec![child]))),
+            }
+        }
+        if !de.el.text.trim().is_empty() {
+            if entries.iter().any(|(name, _)| *name == de.text_field) {
+                return Err(ambiguous(de.el, de.text_field));
+            }
+            entries.push((de.text_field, Entry::Text(&de.el.text)));
+        }
+
+        Ok(Self { entries: entries.into_iter(), value: None, text_field: de.text_field })
+    }
+}
+
+fn ambiguous(el: &Element, name: &str) -> Error {
+    Error(format!("<{}> has more than one attribute, element or text named `{}`", el.name, name))
+}
+
+impl<'de> de::MapAccess<'de> for ElementMap<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(de::value::BorrowedStrDeserializer::new(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take().expect("value requested before key") {
+            Entry::Text(text) => seed.deserialize(TextDeserializer(text)),
+            Entry::Children(elements) => {
+                seed.deserialize(ChildrenDeserializer { elements, text_field: self.text_field })
+            }
+        }
+    }
+}
+
+struct ElementEnum<'e> {
+    variant: &'e str,
+    content: Option<ElementDeserializer<'e>>,
+}
+
+impl<'de> de::EnumAccess<'de> for ElementEnum<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(de::value::BorrowedStrDeserializer::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for ElementEnum<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.content()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self.content()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self.content()?, visitor)
+    }
+}
+
+impl<'e> ElementEnum<'e> {
+    fn content(self) -> Result<ElementDeserializer<'e>, Error> {
+        self.content.ok_or_else(|| Error(format!("variant `{}` must be an element", self.variant)))
+    }
+}
+
+/// 同名的一组子元素，作为序列时每个元素是一项，否则要求只有一个元素
+struct ChildrenDeserializer<'e> {
+    elements: Vec<&'e Element>,
+    text_field: &'e str,
+}
+
+impl<'e> ChildrenDeserializer<'e> {
+    fn single(self) -> Result<ElementDeserializer<'e>, Error> {
+        match self.elements.as_slice() {
+            [el] => Ok(ElementDeserializer { el, text_field: self.text_field }),
+            elements => Err(Error(format!(
+                "expected a single <{}> element, found {}",
+                elements[0].name,
+                elements.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            self.single()?.$method(visitor)
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for ChildrenDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.elements.len() == 1 {
+            self.single()?.deserialize_any(visitor)
+        } else {
+            self.deserialize_seq(visitor)
+        }
+    }
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_identifier
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_map
+        deserialize_ignored_any
+    }
+
+    /// 出现了至少一个元素，所以总是`Some`，里面可能是单个元素也可能是序列
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.single()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.single()?.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text_field = self.text_field;
+        let elements = self.elements.into_iter().map(|el| ElementDeserializer { el, text_field });
+        visitor.visit_seq(de::value::SeqDeserializer::new(elements))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.single()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+}
+
+/// 属性值或者元素的文本，按需要的类型解析
+struct TextDeserializer<'e>(&'e str);
+
+macro_rules! parse_text {
+    ($($method:ident => $visit:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0.trim().parse() {
+                Ok(value) => visitor.$visit(value),
+                Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(self.0), &visitor)),
+            }
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for TextDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    parse_text! {
+        deserialize_bool => visit_bool
+        deserialize_i8 => visit_i8
+        deserialize_i16 => visit_i16
+        deserialize_i32 => visit_i32
+        deserialize_i64 => visit_i64
+        deserialize_u8 => visit_u8
+        deserialize_u16 => visit_u16
+        deserialize_u32 => visit_u32
+        deserialize_u64 => visit_u64
+        deserialize_f32 => visit_f32
+        deserialize_f64 => visit_f64
+        deserialize_char => visit_char
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// 以空白分隔的列表
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(de::value::SeqDeserializer::new(
+            self.0.split_whitespace().map(TextDeserializer),
+        ))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(de::value::BorrowedStrDeserializer::new(self.0.trim()))
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for TextDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+struct ElementSerializer<'s> {
+    name: &'s str,
+    text_field: &'s str,
+}
+
+impl<'s> ElementSerializer<'s> {
+    fn new(name: &'s str) -> Self {
+        Self { name, text_field: TEXT_FIELD }
+    }
+
+    /// 保存为元素文本的字段名，默认为`$text`
+    fn text_field(mut self, text_field: &'s str) -> Self {
+        self.text_field = text_field;
+        self
+    }
+
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Element, Error> {
+        match value.serialize(ValueSerializer { name: self.name, text_field: self.text_field })? {
+            Value::Element(el) => Ok(el),
+            Value::Text(text) => Ok(Element::new(self.name).text(text)),
+            Value::None => Ok(Element::new(self.name)),
+            Value::Elements(_) => Err(Error("a sequence cannot be the root element".to_owned())),
+        }
+    }
+}
+
+/// 序列化一个字段得到的结果，由所在的结构体决定它成为属性还是子元素
+enum Value {
+    None,
+    Text(String),
+    Element(Element),
+    Elements(Vec<Element>),
+}
+
+impl Value {
+    fn into_elements(self, name: &str) -> Vec<Element> {
+        match self {
+            Value::None => vec![],
+            Value::Text(text) => vec![Element::new(name).text(text)],
+            Value::Element(el) => vec![el],
+            Value::Elements(elements) => elements,
+        }
+    }
+}
+
+/// 序列化名为`name`的字段，结构体和变体的名称来自字段名而不是类型名
+#[derive(Clone, Copy)]
+struct ValueSerializer<'s> {
+    name: &'s str,
+    text_field: &'s str,
+}
+
+macro_rules! serialize_text {
+    ($($method:ident: $ty:ty)*) => {$(
+        fn $method(self, value: $ty) -> Result<Value, Error> {
+            Ok(Value::Text(value.to_string()))
+        }
+    )*};
+}
+
+impl<'s> ser::Serializer for ValueSerializer<'s> {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'s>;
+    type SerializeTuple = SeqSerializer<'s>;
+    type SerializeTupleStruct = SeqSerializer<'s>;
+    type SerializeTupleVariant = TupleVariantSerializer<'s>;
+    type SerializeMap = StructSerializer<'s>;
+    type SerializeStruct = StructSerializer<'s>;
+    type SerializeStructVariant = StructSerializer<'s>;
+
+    serialize_text! {
+        serialize_bool: bool serialize_i8: i8 serialize_i16: i16 serialize_i32: i32
+        serialize_i64: i64 serialize_u8: u8 serialize_u16: u16 serialize_u32: u32
+        serialize_u64: u64 serialize_f32: f32 serialize_f64: f64 serialize_char: char
+        serialize_str: &str
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<Value, Error> {
+        Err(Error("bytes are not supported".to_owned()))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Element(Element::new(self.name)))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::Text(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let content = value.serialize(ValueSerializer { name: variant, ..self })?;
+        Ok(Value::Element(Element::new(self.name).children(content.into_elements(variant))))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'s>, Error> {
+        Ok(SeqSerializer { serializer: self, elements: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'s>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'s>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<TupleVariantSerializer<'s>, Error> {
+        let items = ValueSerializer { name: variant, ..self }.serialize_seq(Some(len))?;
+        Ok(TupleVariantSerializer { name: self.name, items })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<StructSerializer<'s>, Error> {
+        Ok(StructSerializer::new(self.name, None, self.text_field))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<StructSerializer<'s>, Error> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<StructSerializer<'s>, Error> {
+        Ok(StructSerializer::new(variant, Some(self.name), self.text_field))
+    }
+}
+
+/// 序列中的每一项都是一个与字段同名的元素
+struct SeqSerializer<'s> {
+    serializer: ValueSerializer<'s>,
+    elements: Vec<Element>,
+}
+
+impl SeqSerializer<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let value = value.serialize(self.serializer)?;
+        self.elements.extend(value.into_elements(self.serializer.name));
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Elements(self.elements))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Elements(self.elements))
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Elements(self.elements))
+    }
+}
+
+/// 元组变体的元素包含每一项，还要再包上一层字段名的元素
+struct TupleVariantSerializer<'s> {
+    name: &'s str,
+    items: SeqSerializer<'s>,
+}
+
+impl ser::SerializeTupleVariant for TupleVariantSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        let variant = Element::new(self.items.serializer.name).children(self.items.elements);
+        Ok(Value::Element(Element::new(self.name).child(variant)))
+    }
+}
+
+/// 结构体、映射和结构体变体，结构体变体的元素还要再包上一层字段名的元素
+struct StructSerializer<'s> {
+    el: Element,
+    wrapper: Option<&'s str>,
+    text_field: &'s str,
+    key: Option<String>,
+}
+
+impl<'s> StructSerializer<'s> {
+    fn new(name: &str, wrapper: Option<&'s str>, text_field: &'s str) -> Self {
+        Self { el: Element::new(name), wrapper, text_field, key: None }
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let value = value.serialize(ValueSerializer { name: key, text_field: self.text_field })?;
+        match value {
+            Value::None => {}
+            Value::Text(text) if key == self.text_field => self.el.text = text,
+            Value::Text(text) => {
+                self.el.set_attribute(key, text);
+            }
+            _ if key == self.text_field => {
+                return Err(Error(format!("`{}` must be serialized as text", key)));
+            }
+            value => self.el.children.extend(value.into_elements(key)),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(Value::Element(match self.wrapper {
+            Some(wrapper) => Element::new(wrapper).child(self.el),
+            None => self.el,
+        }))
+    }
+}
+
+impl ser::SerializeMap for StructSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let serializer = ValueSerializer { name: "", text_field: self.text_field };
+        match key.serialize(serializer)? {
+            Value::Text(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error("map keys must be strings or numbers".to_owned())),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("value serialized before key");
+        self.field(&key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{Parser, element};
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Top {
+        label: String,
+        #[serde(rename = "semi-bottom")]
+        semi_bottom: Option<Bottom>,
+        #[serde(rename = "middle", default)]
+        middles: Vec<Middle>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Middle {
+        level: Option<u32>,
+        #[serde(rename = "bottom", default)]
+        bottoms: Vec<Bottom>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Bottom {
+        label: String,
+        kind: Option<Kind>,
+        tags: Option<Vec<String>>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Leaf,
+        Stub,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Drawing {
+        #[serde(rename = "shape", default)]
+        shapes: Vec<Shape>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Shape {
+        Circle { radius: f64 },
+        Square(Side),
+        Line(Side, Side),
+        Empty,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Side {
+        length: u32,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Paragraph {
+        class: String,
+        #[serde(rename = "$text")]
+        text: String,
+    }
+
+    fn parse(doc: &str) -> Element {
+        element().parse(doc).unwrap().1
+    }
+
+    #[test]
+    fn nested_structs() {
+        let doc = parse(
+            r#"
+            <top label="Top">
+                <semi-bottom label="Bottom" tags="a b"/>
+                <middle level="2">
+                    <bottom label="First" kind="leaf"/>
+                    <bottom label="Second"/>
+                </middle>
+                <middle/>
+            </top>"#,
+        );
+        let top = Top {
+            label: "Top".to_owned(),
+            semi_bottom: Some(Bottom {
+                label: "Bottom".to_owned(),
+                kind: None,
+                tags: Some(vec!["a".to_owned(), "b".to_owned()]),
+            }),
+            middles: vec![
+                Middle {
+                    level: Some(2),
+                    bottoms: vec![
+                        Bottom { label: "First".to_owned(), kind: Some(Kind::Leaf), tags: None },
+                        Bottom { label: "Second".to_owned(), kind: None, tags: None },
+                    ],
+                },
+                Middle { level: None, bottoms: vec![] },
+            ],
+        };
+        assert_eq!(Ok(&top), from_element::<Top>(&doc).as_ref());
+
+        // `tags`是序列，所以序列化为子元素而不是属性
+        let el = to_element("top", &top).unwrap();
+        let tags = el.select("top/semi-bottom/tags").unwrap();
+        assert_eq!(vec!["a", "b"], tags.iter().map(|tag| tag.text.as_str()).collect::<Vec<_>>());
+        assert_eq!(Ok(top), from_element(&el));
+    }
+
+    #[test]
+    fn enums() {
+        let doc = parse(
+            r#"
+            <drawing>
+                <shape><circle radius="1.5"/></shape>
+                <shape><square length="2"/></shape>
+                <shape><line><from length="1"/><to length="3"/></line></shape>
+                <shape><empty/></shape>
+            </drawing>"#,
+        );
+        let drawing = Drawing {
+            shapes: vec![
+                Shape::Circle { radius: 1.5 },
+                Shape::Square(Side { length: 2 }),
+                Shape::Line(Side { length: 1 }, Side { length: 3 }),
+                Shape::Empty,
+            ],
+        };
+        assert_eq!(Ok(&drawing), from_element::<Drawing>(&doc).as_ref());
+        let el = to_element("drawing", &drawing).unwrap();
+        assert_eq!(
+            Element::new("shape").child(
+                Element::new("line")
+                    .child(Element::new("line").attr("length", "1"))
+                    .child(Element::new("line").attr("length", "3"))
+            ),
+            el.children[2]
+        );
+        assert_eq!(Ok(drawing), from_element(&el));
+    }
+
+    #[test]
+    fn text_field() {
+        let el = Element::new("p").attr("class", "note").text("Hello");
+        let paragraph = Paragraph { class: "note".to_owned(), text: "Hello".to_owned() };
+        assert_eq!(Ok(&paragraph), from_element::<Paragraph>(&el).as_ref());
+        assert_eq!(Ok(el.clone()), to_element("p", &paragraph));
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Note {
+            content: String,
+        }
+        let note = Note { content: "Hello".to_owned() };
+        assert_eq!(
+            Ok(&note),
+            Note::deserialize(ElementDeserializer::new(&el).text_field("content")).as_ref()
+        );
+        assert_eq!(
+            Ok(Element::new("p").text("Hello")),
+            ElementSerializer::new("p").text_field("content").serialize(&note)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let doc = parse("<top/>");
+        assert_eq!(Err(Error("missing field `label`".to_owned())), from_element::<Top>(&doc));
+
+        let doc = parse(r#"<middle level="high"/>"#);
+        assert_eq!(
+            Err(Error("invalid value: string \"high\", expected u32".to_owned())),
+            from_element::<Middle>(&doc)
+        );
+
+        let doc =
+            parse(r#"<top label="Top"><semi-bottom label="1"/><semi-bottom label="2"/></top>"#);
+        assert_eq!(
+            Err(Error("expected a single <semi-bottom> element, found 2".to_owned())),
+            from_element::<Top>(&doc)
+        );
+
+        // 同名的属性与子元素无法区分
+        let doc = parse(r#"<top label="Top"><label/></top>"#);
+        assert_eq!(
+            Err(Error(
+                "<top> has more than one attribute, element or text named `label`".to_owned()
+            )),
+            from_element::<Top>(&doc)
+        );
+        let el = Element::new("p").attr("content", "a").text("b");
+        assert_eq!(
+            Err(Error(
+                "<p> has more than one attribute, element or text named `content`".to_owned()
+            )),
+            BTreeMap::<String, String>::deserialize(
+                ElementDeserializer::new(&el).text_field("content")
+            )
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! 带有用户状态的解析器
+//! 状态随输入一起传递：`Stateful`把任意的`Input`和一个`RefCell<S>`放在一起，本身也是`Input`，
+//! 文本输入包装之后仍然是`Text`，所以所有的组合器和基础解析器都可以直接使用，
+//! 需要读写状态的地方使用`map_with_state`和`pred_with_state`，
+//! 可以用来统计行号、驻留名称或者记录已声明的标识符
+//!
+//! 回溯时状态必须一起恢复，否则失败分支做过的修改会泄漏给后面的分支，
+//! 所以`either`、`optional`、`pred`以及重复的组合器通过`Input::attempt`在尝试之前记下状态的检查点，
+//! 失败时回滚到检查点，这要求`S: Rollback`
+//! 没有经过这些组合器的失败（例如`pair`的第二个解析器失败）不会恢复状态，
+//! 由外层的回溯点负责
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::Hash,
+};
+
+use crate::{Input, ParseResult, Parser, Text, streaming::Needed};
+
+/// 可以回滚的状态
+/// 检查点只需要记录足以撤销之后的修改的信息，例如只追加的表记下长度，回滚时截断，
+/// 不需要在每次尝试之前复制整个状态
+trait Rollback {
+    type Checkpoint;
+
+    fn checkpoint(&self) -> Self::Checkpoint;
+
+    fn rollback(&mut self, checkpoint: Self::Checkpoint);
+}
+
+/// 把`Vec`当作只追加的表，回滚时丢弃检查点之后追加的元素，对已有元素的修改不会被撤销
+impl<T> Rollback for Vec<T> {
+    type Checkpoint = usize;
+
+    fn checkpoint(&self) -> usize {
+        self.len()
+    }
+
+    fn rollback(&mut self, len: usize) {
+        self.truncate(len);
+    }
+}
+
+/// 计数器，例如行号
+impl Rollback for usize {
+    type Checkpoint = usize;
+
+    fn checkpoint(&self) -> usize {
+        *self
+    }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        *self = checkpoint;
+    }
+}
+
+/// 映射和集合没有顺序，无法只记下长度，检查点是整个状态的副本，
+/// 状态很大而回溯又很频繁时，可以改用只追加的`Vec`
+macro_rules! rollback_by_clone {
+    ($([$($generics:tt)*] $ty:ty,)*) => {$(
+        impl<$($generics)*> Rollback for $ty {
+            type Checkpoint = Self;
+
+            fn checkpoint(&self) -> Self {
+                self.clone()
+            }
+
+            fn rollback(&mut self, checkpoint: Self) {
+                *self = checkpoint;
+            }
+        }
+    )*};
+}
+
+rollback_by_clone! {
+    [K: Clone + Eq + Hash, V: Clone] HashMap<K, V>,
+    [T: Clone + Eq + Hash] HashSet<T>,
+    [K: Clone + Ord, V: Clone] BTreeMap<K, V>,
+    [T: Clone + Ord] BTreeSet<T>,
+}
+
+/// 带有状态的输入，复制输入时共享同一份状态
+struct Stateful<'s, I, S> {
+    input: I,
+    state: &'s RefCell<S>,
+}
+
+impl<'s, I, S> Stateful<'s, I, S> {
+    fn new(input: I, state: &'s RefCell<S>) -> Self {
+        Self { input, state }
+    }
+
+    fn with(self, input: I) -> Self {
+        Self { input, state: self.state }
+    }
+}
+
+impl<I: Copy, S> Clone for Stateful<'_, I, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I: Copy, S> Copy for Stateful<'_, I, S> {}
+
+impl<I: Input, S: Rollback> Input for Stateful<'_, I, S> {
+    fn address(&self) -> Option<usize> {
+        self.input.address()
+    }
+
+    fn needed(&self) -> Option<Needed> {
+        self.input.needed()
+    }
+
+    fn attempt<O>(
+        self,
+        parse: impl FnOnce(Self) -> Result<(Self, O), Self>,
+    ) -> Result<(Self, O), Self> {
+        let checkpoint = self.state.borrow().checkpoint();
+        let result = parse(self);
+        if result.is_err() {
+            self.state.borrow_mut().rollback(checkpoint);
+        }
+        result
+    }
+}
+
+impl<'a, I: Text<'a>, S: Rollback> Text<'a> for Stateful<'_, I, S> {
+    fn as_str(&self) -> &'a str {
+        self.input.as_str()
+    }
+
+    fn advance(self, len: usize) -> Self {
+        self.with(self.input.advance(len))
+    }
+
+    fn is_partial(&self) -> bool {
+        self.input.is_partial()
+    }
+
+    fn incomplete(self, needed: Needed) -> Self {
+        self.with(self.input.incomplete(needed))
+    }
+}
+
+/// 以`state`为状态运行解析器，结果中的剩余输入去掉了状态
+fn parse_with_state<'a, 's, P, A, I, S>(
+    parser: &P,
+    input: I,
+    state: &'s RefCell<S>,
+) -> ParseResult<'a, A, I>
+where
+    I: Input,
+    S: Rollback,
+    P: Parser<'a, A, Stateful<'s, I, S>>,
+{
+    match parser.parse(Stateful::new(input, state)) {
+        Ok((rest, result)) => Ok((rest.input, result)),
+        Err(rest) => Err(rest.input),
+    }
+}
+
+/// 解析成功后用结果更新状态，并把结果换成`update`的返回值
+fn map_with_state<'a, 's, P, F, A, B, I, S>(
+    parser: P,
+    update: F,
+) -> impl Parser<'a, B, Stateful<'s, I, S>>
+where
+    I: Input,
+    S: Rollback + 's,
+    P: Parser<'a, A, Stateful<'s, I, S>>,
+    F: Fn(A, &mut S) -> B,
+{
+    move |input: Stateful<'s, I, S>| {
+        let (next_input, result) = parser.parse(input)?;
+        let result = update(result, &mut input.state.borrow_mut());
+        Ok((next_input, result))
+    }
+}
+
+/// 谓词可以同时检查结果和状态，被拒绝的结果对状态的修改会被撤销
+fn pred_with_state<'a, 's, P, F, A, I, S>(
+    parser: P,
+    predicate: F,
+) -> impl Parser<'a, A, Stateful<'s, I, S>>
+where
+    I: Input,
+    S: Rollback + 's,
+    P: Parser<'a, A, Stateful<'s, I, S>>,
+    F: Fn(&A, &S) -> bool,
+{
+    move |input: Stateful<'s, I, S>| {
+        input.attempt(|input| match parser.parse(input) {
+            Ok((next_input, value)) if predicate(&value, &input.state.borrow()) => {
+                Ok((next_input, value))
+            }
+            Err(rest) if rest.needed().is_some() => Err(rest),
+            _ => Err(input),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        either, identifier, left, many0, match_literal, newline, one_or_more, optional, pair,
+        recognize, right, space0, take_while, zero_or_more,
+    };
+
+    /// 已经声明的变量
+    type Scope = Vec<String>;
+
+    type Source<'a, 's, S = Scope> = Stateful<'s, &'a str, S>;
+
+    /// `let name;`
+    fn declaration<'a, 's>() -> impl Parser<'a, (), Source<'a, 's>> {
+        let name = right(match_literal("let "), identifier);
+        map_with_state(left(name, match_literal(";")), |name, scope: &mut Scope| scope.push(name))
+    }
+
+    /// `name;`，只能使用已经声明的变量
+    fn usage<'a, 's>() -> impl Parser<'a, String, Source<'a, 's>> {
+        pred_with_state(left(identifier, match_literal(";")), |name, scope: &Scope| {
+            scope.contains(name)
+        })
+    }
+
+    fn program<'a, 's>() -> impl Parser<'a, Vec<Option<String>>, Source<'a, 's>> {
+        let statement = either(crate::map(declaration(), |_| None), crate::map(usage(), Some));
+        zero_or_more(right(space0(), left(statement, space0())))
+    }
+
+    #[test]
+    fn declared_identifiers() {
+        let scope = RefCell::new(Scope::new());
+        assert_eq!(
+            Ok(("", vec![None, Some("x".to_owned()), None, Some("y".to_owned())])),
+            parse_with_state(&program(), "let x; x; let y; y;", &scope)
+        );
+        assert_eq!(vec!["x", "y"], scope.into_inner());
+
+        let scope = RefCell::new(Scope::new());
+        assert_eq!(
+            Ok(("y; let y;", vec![None])),
+            parse_with_state(&program(), "let x; y; let y;", &scope)
+        );
+    }
+
+    #[test]
+    fn rollback_on_backtracking() {
+        // 第一个分支已经声明了`x`，之后在`!`处失败，这个声明不能留下来
+        let scope = RefCell::new(Scope::new());
+        let declare_then_bang =
+            map_with_state(identifier, |name, scope: &mut Scope| scope.push(name));
+        let parser = either(
+            crate::map(left(declare_then_bang, match_literal("!")), |_| "declared"),
+            crate::map(identifier, |_| "plain"),
+        );
+        assert_eq!(Ok((";", "plain")), parse_with_state(&parser, "x;", &scope));
+        assert!(scope.borrow().is_empty());
+
+        assert_eq!(Ok(("", "declared")), parse_with_state(&parser, "x!", &scope));
+        assert_eq!(vec!["x"], *scope.borrow());
+
+        // 同样适用于`optional`和重复的组合器最后一次失败的尝试
+        let scope = RefCell::new(Scope::new());
+        assert_eq!(Ok(("let z", vec![None])), parse_with_state(&program(), "let x; let z", &scope));
+        assert_eq!(vec!["x"], scope.into_inner());
+    }
+
+    /// 映射和集合回滚到检查点时的内容
+    #[test]
+    fn rollback_maps_and_sets() {
+        let defined = RefCell::new(HashMap::new());
+        let seen = RefCell::new(BTreeSet::new());
+
+        // `name=value;`，最后一个定义缺少`;`，它写入的值不能留下来
+        let definition = map_with_state(
+            pair(identifier, right(match_literal("="), take_while(char::is_alphanumeric))),
+            |(name, value), defined: &mut HashMap<String, String>| {
+                defined.insert(name.clone(), value.to_owned());
+                name
+            },
+        );
+        let parser = many0(left(definition, match_literal(";")));
+        assert_eq!(
+            Ok(("b=2", Vec::from(["a".to_owned()]))),
+            parse_with_state(&parser, "a=1;b=2", &defined)
+        );
+        assert_eq!(HashMap::from([("a".to_owned(), "1".to_owned())]), *defined.borrow());
+
+        let word = map_with_state(
+            recognize(take_while(char::is_alphabetic)),
+            |word, seen: &mut BTreeSet<&str>| {
+                seen.insert(word);
+            },
+        );
+        let parser = optional(left(word, match_literal("!")));
+        assert_eq!(Ok(("abc", None)), parse_with_state(&parser, "abc", &seen));
+        assert!(seen.borrow().is_empty());
+    }
+
+    /// 每一行记录它的行号
+    #[test]
+    fn line_numbers() {
+        let rest_of_line = take_while(|c| c != '\n' && c != '\r');
+        let line =
+            map_with_state(left(rest_of_line, optional(newline())), |text, line: &mut usize| {
+                *line += 1;
+                (*line, text)
+            });
+        let line_number = RefCell::new(0);
+        assert_eq!(
+            Ok(("", vec![(1, "first"), (2, "second"), (3, "third")])),
+            parse_with_state(
+                &one_or_more(crate::pred(line, |(_, text)| !text.is_empty())),
+                "first\nsecond\r\nthird",
+                &line_number
+            )
+        );
+        assert_eq!(3, line_number.into_inner());
+    }
+}
@@ -0,0 +1,444 @@
+//! 分块输入的增量解析
+//! `Partial`是分块到达的文本，基础解析器在它的末尾还无法做出判断时，失败返回的剩余输入带有`Needed`，
+//! 组合器原样向上传递它，`either`和`zero_or_more`也不会把它当作失败而去尝试别的分支，
+//! 所以流式解析与`element`使用的是同一套解析器
+//!
+//! `Driver`每次解析一个标记：开始标记、自闭合的元素或者结束标记，还没有结束的元素保存在显式的栈中，
+//! 收到新数据时从上一个不完整的标记继续，已经解析的字节会从缓冲区中移除，
+//! 所以嵌套再深也不会递归，嵌套深度和缓冲的字节数都有上限
+
+use std::{rc::Rc, str};
+
+use crate::{
+    Element, Input, Parser, Text, close_element, either, map, open_element, single_element,
+    xml::{Context, Limits, XmlError, XmlErrorKind},
+};
+
+/// 还需要多少输入才能继续
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Needed {
+    Unknown,
+    /// 至少还需要的字节数
+    Size(usize),
+}
+
+/// 分块到达的文本，后面可能还有更多的数据
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Partial<'a> {
+    text: &'a str,
+    /// 只有解析失败时返回的剩余输入才会带有，见`Input::needed`
+    needed: Option<Needed>,
+}
+
+impl<'a> Partial<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        Self { text, needed: None }
+    }
+}
+
+impl Input for Partial<'_> {
+    fn address(&self) -> Option<usize> {
+        self.text.address()
+    }
+
+    fn needed(&self) -> Option<Needed> {
+        self.needed
+    }
+}
+
+impl<'a> Text<'a> for Partial<'a> {
+    fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    fn advance(self, len: usize) -> Self {
+        Self::new(&self.text[len..])
+    }
+
+    fn is_partial(&self) -> bool {
+        true
+    }
+
+    /// 还需要更多输入时，失败的位置是已有数据的末尾
+    fn incomplete(self, needed: Needed) -> Self {
+        Self { text: &self.text[self.text.len()..], needed: Some(needed) }
+    }
+}
+
+/// `Driver`每一步解析的标记
+enum Token {
+    Open(Element),
+    Empty(Element),
+    Close(String),
+}
+
+/// `<`之后还不知道是结束标记还是元素时，`either`会等待更多的输入
+fn token<'a>(ctx: &Rc<Context>) -> impl Parser<'a, Token, Partial<'a>> {
+    let element =
+        either(map(single_element(ctx), Token::Empty), map(open_element(ctx), Token::Open));
+    either(map(close_element(ctx), Token::Close), element)
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Feed<Output> {
+    Done(Output),
+    NeedMore(Needed),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FeedError {
+    /// 语法错误或者超出了`Limits`
+    Xml(XmlError),
+    InvalidUtf8 {
+        offset: usize,
+    },
+    /// 一个标记还没有结束，缓冲的数据就超过了`max_buffered`，`offset`是这个标记的开头
+    BufferFull {
+        offset: usize,
+    },
+    /// 输入结束时文档还不完整
+    UnexpectedEof,
+}
+
+/// 默认最多缓冲的字节数
+const MAX_BUFFERED: usize = 1024 * 1024;
+
+/// 把分块到达的字节交给流式解析器
+/// 一个文档解析完成后，它之后的数据会留在缓冲区中作为下一个文档的开头，
+/// 错误中的偏移都相对于整个输入流的开头，而不是当前的文档
+pub(crate) struct Driver {
+    /// `Context`在每次解析时新建，`Driver`不持有`Rc`，可以在线程之间移动
+    limits: Limits,
+    /// 还没有解析的字节，也就是不完整的标记
+    buffer: Vec<u8>,
+    max_buffered: usize,
+    /// 已经开始、还没有结束的元素，栈顶是最内层的元素
+    open: Vec<Element>,
+    /// 缓冲区的开头在输入流中的偏移
+    offset: usize,
+    /// 缓冲区至少达到这个长度才值得重新解析
+    required: usize,
+}
+
+impl Driver {
+    /// 嵌套深度、属性和名称使用`limits`，分块的输入没有总长度，`max_input_len`不适用，
+    /// 取而代之的是一个标记最多可以缓冲的字节数，见`max_buffered`
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            buffer: vec![],
+            max_buffered: MAX_BUFFERED,
+            open: vec![],
+            offset: 0,
+            required: 0,
+        }
+    }
+
+    /// 默认为1 MiB，过长的属性值这样的标记在到齐之前就会失败，而不是一直缓冲下去
+    pub(crate) fn max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Result<Feed<Element>, FeedError> {
+        self.buffer.extend_from_slice(chunk);
+        if self.buffer.len() < self.required {
+            return Ok(Feed::NeedMore(Needed::Size(self.required - self.buffer.len())));
+        }
+
+        let (consumed, result) = self.parse();
+        self.buffer.drain(..consumed);
+        self.offset += consumed;
+        match result {
+            Ok(Feed::Done(_)) => self.required = 0,
+            Ok(Feed::NeedMore(_)) if self.buffer.len() > self.max_buffered => {
+                return Err(FeedError::BufferFull { offset: self.offset });
+            }
+            Ok(Feed::NeedMore(needed)) => {
+                self.required = self.buffer.len()
+                    + match needed {
+                        Needed::Size(size) => size,
+                        Needed::Unknown => 1,
+                    };
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// 从缓冲区的开头依次解析标记，直到文档结束、出错或者需要更多的输入，
+    /// 返回已经解析的字节数
+    fn parse(&mut self) -> (usize, Result<Feed<Element>, FeedError>) {
+        // 数据块可能在多字节字符的中间断开，末尾不完整的字符留到下一次
+        let input = match str::from_utf8(&self.buffer) {
+            Ok(input) => input,
+            Err(err) if err.error_len().is_none() => {
+                str::from_utf8(&self.buffer[..err.valid_up_to()]).unwrap()
+            }
+            Err(err) => {
+                return (
+                    0,
+                    Err(FeedError::InvalidUtf8 { offset: self.offset + err.valid_up_to() }),
+                );
+            }
+        };
+        // 致命错误记录的是地址，换算为输入流中的偏移
+        let (base, start) = (self.offset, input.as_ptr() as usize);
+        let error = move |kind, address: usize| {
+            FeedError::Xml(XmlError { kind, offset: base + address - start })
+        };
+
+        let ctx = Context::new(self.limits.clone());
+        let parser = token(&ctx);
+        let mut rest = input;
+        loop {
+            rest = rest.trim_start();
+            let consumed = input.len() - rest.len();
+            if rest.is_empty() {
+                return (consumed, Ok(Feed::NeedMore(Needed::Unknown)));
+            }
+
+            ctx.reset();
+            let result = parser.parse(Partial::new(rest));
+            if let Some((kind, address)) = ctx.error() {
+                return (consumed, Err(error(kind, address)));
+            }
+            let (next, token) = match result {
+                Ok(parsed) => parsed,
+                Err(failed) => match failed.needed() {
+                    Some(needed) => return (consumed, Ok(Feed::NeedMore(needed))),
+                    None => {
+                        let address = failed.as_str().as_ptr() as usize;
+                        return (consumed, Err(error(XmlErrorKind::Syntax, address)));
+                    }
+                },
+            };
+
+            let address = rest.as_ptr() as usize;
+            let closed = match token {
+                Token::Open(el) => {
+                    if !ctx.check_depth(self.open.len() + 1, rest) {
+                        return (consumed, Err(error(XmlErrorKind::TooDeep, address)));
+                    }
+                    self.open.push(el);
+                    None
+                }
+                Token::Empty(el) => Some(el),
+                // 结束标记必须与开始标记一致，否则在结束标记处出错
+                Token::Close(name) => match self.open.pop() {
+                    Some(el) if el.name == name => Some(el),
+                    _ => return (consumed, Err(error(XmlErrorKind::Syntax, address))),
+                },
+            };
+            rest = next.as_str();
+
+            match (closed, self.open.last_mut()) {
+                (Some(el), Some(parent)) => parent.children.push(el),
+                (Some(el), None) => return (input.len() - rest.len(), Ok(Feed::Done(el))),
+                (None, _) => {}
+            }
+        }
+    }
+
+    /// 输入已经结束，不能还有没有结束的元素，缓冲区中除了空白也不应再有其他内容
+    pub(crate) fn finish(self) -> Result<(), FeedError> {
+        match str::from_utf8(&self.buffer) {
+            Ok(rest) if self.open.is_empty() && rest.trim().is_empty() => Ok(()),
+            _ => Err(FeedError::UnexpectedEof),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ParseResult, match_literal, optional, pair, quoted_string, right, take_while, zero_or_more,
+    };
+
+    const DOC: &str = r#"
+        <top label="Top">
+            <semi-bottom label="Bottom"/>
+            <middle>
+                <子元素 label="中文"/>
+            </middle>
+        </top>"#;
+
+    /// 解析失败时只关心还需要的输入量
+    fn needed<A>(result: ParseResult<'_, A, Partial<'_>>) -> Option<Needed> {
+        result.err()?.needed()
+    }
+
+    fn syntax(offset: usize) -> FeedError {
+        FeedError::Xml(XmlError { kind: XmlErrorKind::Syntax, offset })
+    }
+
+    #[test]
+    fn primitives() {
+        let literal = match_literal("<?xml");
+        assert_eq!(Ok((Partial::new(" ?>"), ())), literal.parse(Partial::new("<?xml ?>")));
+        assert_eq!(Some(Needed::Size(2)), needed(literal.parse(Partial::new("<?x"))));
+        assert_eq!(Err(Partial::new("<a")), literal.parse(Partial::new("<a")));
+        // 完整的输入到了末尾就是失败
+        assert_eq!(Err("<?x"), match_literal("<?xml").parse("<?x"));
+
+        let letters = take_while(char::is_alphabetic);
+        assert_eq!(Some(Needed::Unknown), needed(letters.parse(Partial::new("abc"))));
+        assert_eq!(Ok((Partial::new(" d"), "abc")), letters.parse(Partial::new("abc d")));
+        assert_eq!(Ok(("", "abc")), take_while(char::is_alphabetic).parse("abc"));
+
+        let string = quoted_string();
+        assert_eq!(Some(Needed::Unknown), needed(string.parse(Partial::new(r#""unterminated"#))));
+        assert_eq!(Some(Needed::Size(1)), needed(string.parse(Partial::new(""))));
+        assert_eq!(
+            Ok((Partial::new(""), "done".to_owned())),
+            string.parse(Partial::new(r#""done""#))
+        );
+        assert_eq!(Err(Partial::new("x")), string.parse(Partial::new("x")));
+    }
+
+    #[test]
+    fn combinators_keep_incomplete() {
+        // 第一个分支还无法判断时不会尝试第二个
+        let parser = either(match_literal("abc"), match_literal("ab"));
+        assert_eq!(Some(Needed::Size(1)), needed(parser.parse(Partial::new("ab"))));
+        assert_eq!(Ok(("", ())), either(match_literal("abc"), match_literal("ab")).parse("ab"));
+
+        // 末尾之后可能还有更多的项
+        let parser = zero_or_more(match_literal("ab"));
+        assert_eq!(Some(Needed::Size(2)), needed(parser.parse(Partial::new("abab"))));
+        assert_eq!(Ok((Partial::new("x"), vec![(), ()])), parser.parse(Partial::new("ababx")));
+    }
+
+    #[test]
+    fn incomplete_through_chains() {
+        // `pred`、`map`和`and_then`不会把还需要更多输入的失败当作普通的失败
+        let name = || {
+            right(match_literal("<"), take_while(char::is_alphabetic))
+                .pred(|name| name.len() > 1)
+                .map(str::len)
+        };
+        let parser = either(name(), match_literal("<").map(|_| 0));
+        assert_eq!(Some(Needed::Unknown), needed(parser.parse(Partial::new("<ab"))));
+        assert_eq!(Ok((Partial::new(">"), 2)), parser.parse(Partial::new("<ab>")));
+        // 谓词不成立是普通的失败，`either`会尝试第二个分支
+        assert_eq!(Ok((Partial::new("a>"), 0)), parser.parse(Partial::new("<a>")));
+
+        let parser = optional(name().and_then(|len| match_literal("/>").map(move |_| len)));
+        assert_eq!(Some(Needed::Size(1)), needed(parser.parse(Partial::new("<ab/"))));
+        assert_eq!(Some(Needed::Unknown), needed(parser.parse(Partial::new("<ab"))));
+        assert_eq!(Ok((Partial::new(""), Some(2))), parser.parse(Partial::new("<ab/>")));
+        assert_eq!(Ok((Partial::new("<ab>"), None)), parser.parse(Partial::new("<ab>")));
+
+        // XML的标记同样如此，`<a`之后可能还有名称的其余部分或者属性
+        let ctx = Context::new(Limits::default());
+        let parser = pair(token(&ctx).map(|_| ()), match_literal("x"));
+        for prefix in ["<", "<a", "<a b", "<a b=\"1\"", "<a/", "</", "</a"] {
+            assert!(needed(parser.parse(Partial::new(prefix))).is_some(), "{}", prefix);
+        }
+    }
+
+    #[test]
+    fn every_prefix_is_incomplete() {
+        let (_, expected) = crate::element().parse(DOC).unwrap();
+        for end in (0..DOC.len()).filter(|end| DOC.is_char_boundary(*end)) {
+            let mut driver = Driver::new(Limits::default());
+            assert!(
+                matches!(driver.feed(&DOC.as_bytes()[..end]), Ok(Feed::NeedMore(_))),
+                "prefix of length {} should be incomplete",
+                end
+            );
+        }
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Ok(Feed::Done(expected)), driver.feed(DOC.as_bytes()));
+    }
+
+    #[test]
+    fn feed_chunks() {
+        let (_, expected) = crate::element().parse(DOC).unwrap();
+        // 每种块大小都会在不同的位置断开，包括多字节字符的中间
+        for size in 1..=8 {
+            let mut driver = Driver::new(Limits::default());
+            let mut result = None;
+            for chunk in DOC.as_bytes().chunks(size) {
+                if let Feed::Done(el) = driver.feed(chunk).unwrap() {
+                    result = Some(el);
+                }
+                // 已经解析的标记不会留在缓冲区中
+                assert!(driver.buffer.len() < 32);
+            }
+            assert_eq!(Some(&expected), result.as_ref());
+            assert_eq!(Ok(()), driver.finish());
+        }
+    }
+
+    #[test]
+    fn consecutive_documents() {
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Ok(Feed::Done(Element::new("a"))), driver.feed(b"<a/>\n<b>"));
+        // `<b>`之后可能还有空白或者子元素，`</`之后至少还需要一个字节的名称
+        assert_eq!(Ok(Feed::NeedMore(Needed::Unknown)), driver.feed(b""));
+        assert!(driver.buffer.is_empty());
+        assert_eq!(Ok(Feed::NeedMore(Needed::Size(1))), driver.feed(b"</"));
+        assert_eq!(Ok(Feed::Done(Element::new("b"))), driver.feed(b"b>"));
+        assert_eq!(Ok(Feed::NeedMore(Needed::Size(1))), driver.feed(b"<c"));
+        assert_eq!(Err(FeedError::UnexpectedEof), driver.finish());
+
+        // 偏移从输入流的开头算起，包括前面的文档以及文档之间的空白
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Ok(Feed::Done(Element::new("a"))), driver.feed(b"<a/>\n\n<b>"));
+        assert_eq!(Err(syntax(9)), driver.feed(b"</c>"));
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Ok(Feed::Done(Element::new("a"))), driver.feed(b"<a/>"));
+        assert_eq!(Err(FeedError::InvalidUtf8 { offset: 8 }), driver.feed(b"  <b\xff"));
+
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Ok(Feed::NeedMore(Needed::Unknown)), driver.feed(b"<a> "));
+        assert_eq!(Err(FeedError::UnexpectedEof), driver.finish());
+    }
+
+    #[test]
+    fn feed_errors() {
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Ok(Feed::NeedMore(Needed::Unknown)), driver.feed(b"<top>\n    <bottom/>\n"));
+        assert_eq!(Err(syntax(20)), driver.feed(b"</middle>"));
+
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Err(syntax(3)), driver.feed(b"<a>hi</a>"));
+
+        let mut driver = Driver::new(Limits::default());
+        assert_eq!(Err(FeedError::InvalidUtf8 { offset: 2 }), driver.feed(b"<a\xff/>"));
+    }
+
+    #[test]
+    fn deep_nesting_fails_cleanly() {
+        let depth = 100_000;
+        let doc = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        let mut driver = Driver::new(Limits::default());
+        let too_deep = FeedError::Xml(XmlError { kind: XmlErrorKind::TooDeep, offset: 128 * 3 });
+        assert_eq!(
+            Some(Err(too_deep)),
+            doc.as_bytes().chunks(4096).map(|chunk| driver.feed(chunk)).find(Result::is_err)
+        );
+
+        // 没有深度限制时，打开的元素保存在栈中，不会递归
+        let mut driver = Driver::new(Limits::default().max_depth(usize::MAX));
+        assert_eq!(Ok(Feed::NeedMore(Needed::Unknown)), driver.feed(&doc.as_bytes()[..depth * 3]));
+        assert_eq!(depth, driver.open.len());
+    }
+
+    #[test]
+    fn buffer_limit() {
+        let mut driver = Driver::new(Limits::default()).max_buffered(16);
+        assert_eq!(Ok(Feed::NeedMore(Needed::Unknown)), driver.feed(b"<a>\n  <b label=\""));
+        assert_eq!(Err(FeedError::BufferFull { offset: 6 }), driver.feed(&[b'x'; 16]));
+
+        // 空白和已经完成的标记不占用缓冲区
+        let mut driver = Driver::new(Limits::default()).max_buffered(16);
+        assert_eq!(Ok(Feed::NeedMore(Needed::Unknown)), driver.feed(&[b' '; 64]));
+        assert_eq!(
+            Ok(Feed::NeedMore(Needed::Unknown)),
+            driver.feed("<a><b/><b/><b/><b/>".as_bytes())
+        );
+    }
+}
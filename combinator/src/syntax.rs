@@ -0,0 +1,586 @@
+//! 语法描述
+//! `pair`、`either`、`zero_or_more`等组合器由子解析器的描述组合出自己的描述，
+//! 由此生成的EBNF和铁路图（railroad diagram）与解析器来自同一份代码，不会与实现脱节
+//!
+//! 只有叶子需要手工描述：`match_literal`自带描述，`identifier`这样的函数用`describe`补上
+//! `named`的解析器在描述中是一条规则，引用其他规则时使用`rule`或者`recursive`，`Grammar`沿着引用收集整个语法
+//!
+//! EBNF使用XML规范的写法：https://www.w3.org/TR/xml/#sec-notation
+
+use std::fmt::{self, Write};
+
+use crate::{Input, ParseResult, Parser};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Syntax {
+    /// 原样匹配的文本
+    Literal(String),
+    /// 不再展开的终结符，例如`Name`或者`S`
+    Terminal(String),
+    /// 对另一条规则的引用
+    Rule(Rule),
+    Sequence(Vec<Syntax>),
+    Choice(Vec<Syntax>),
+    Optional(Box<Syntax>),
+    ZeroOrMore(Box<Syntax>),
+    OneOrMore(Box<Syntax>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Rule {
+    name: &'static str,
+    definition: Definition,
+}
+
+/// `named`的规则直接带着定义，`rule`引用的规则只在需要时才生成定义，所以规则之间可以互相递归
+#[derive(Clone, Debug)]
+enum Definition {
+    Ready(Option<Box<Syntax>>),
+    Lazy(fn() -> Option<Syntax>),
+}
+
+impl Rule {
+    fn definition(&self) -> Option<Syntax> {
+        let syntax = match &self.definition {
+            Definition::Ready(syntax) => syntax.as_deref()?.clone(),
+            Definition::Lazy(definition) => definition()?,
+        };
+        // 引用的函数返回的是同名的`named`规则时，取出它的定义
+        match syntax {
+            Syntax::Rule(rule) if rule.name == self.name => rule.definition(),
+            syntax => Some(syntax),
+        }
+    }
+}
+
+impl PartialEq for Rule {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Rule {}
+
+impl Syntax {
+    pub(crate) fn literal(text: &str) -> Self {
+        Syntax::Literal(text.to_owned())
+    }
+
+    pub(crate) fn terminal(name: &str) -> Self {
+        Syntax::Terminal(name.to_owned())
+    }
+
+    pub(crate) fn rule(name: &'static str, definition: Option<Syntax>) -> Self {
+        Syntax::Rule(Rule { name, definition: Definition::Ready(definition.map(Box::new)) })
+    }
+
+    /// 嵌套的序列会被展开，只有一项时就是这一项本身
+    pub(crate) fn sequence(items: impl IntoIterator<Item = Syntax>) -> Self {
+        let mut items = items
+            .into_iter()
+            .flat_map(|item| match item {
+                Syntax::Sequence(items) => items,
+                item => vec![item],
+            })
+            .collect::<Vec<_>>();
+        if items.len() == 1 { items.remove(0) } else { Syntax::Sequence(items) }
+    }
+
+    /// 只有一个候选项时就是这一项本身
+    pub(crate) fn choice(alternatives: impl IntoIterator<Item = Syntax>) -> Self {
+        let mut alternatives = alternatives.into_iter().collect::<Vec<_>>();
+        if alternatives.len() == 1 { alternatives.remove(0) } else { Syntax::Choice(alternatives) }
+    }
+
+    pub(crate) fn optional(inner: Syntax) -> Self {
+        Syntax::Optional(Box::new(inner))
+    }
+
+    pub(crate) fn zero_or_more(inner: Syntax) -> Self {
+        Syntax::ZeroOrMore(Box::new(inner))
+    }
+
+    pub(crate) fn one_or_more(inner: Syntax) -> Self {
+        Syntax::OneOrMore(Box::new(inner))
+    }
+
+    /// 按出现的顺序访问引用的规则
+    fn for_each_rule(&self, f: &mut impl FnMut(&Rule)) {
+        match self {
+            Syntax::Literal(_) | Syntax::Terminal(_) => {}
+            Syntax::Rule(rule) => f(rule),
+            Syntax::Sequence(items) | Syntax::Choice(items) => {
+                items.iter().for_each(|item| item.for_each_rule(f))
+            }
+            Syntax::Optional(inner) | Syntax::ZeroOrMore(inner) | Syntax::OneOrMore(inner) => {
+                inner.for_each_rule(f)
+            }
+        }
+    }
+}
+
+/// 对另一条规则的引用，见`rule`
+pub(crate) struct RuleRef<P> {
+    name: &'static str,
+    parser: P,
+    definition: fn() -> Option<Syntax>,
+}
+
+/// 引用名为`name`的规则，`parser`通常在每次解析时才构造这条规则，
+/// 例如`grammar!`中的`rule("signed", |input| signed().parse(input), || signed().syntax())`，
+/// 描述中只出现规则的名称，它的定义由`Grammar`另外收集
+pub(crate) fn rule<P>(
+    name: &'static str,
+    parser: P,
+    definition: fn() -> Option<Syntax>,
+) -> RuleRef<P> {
+    RuleRef { name, parser, definition }
+}
+
+impl<'a, P, Output, I> Parser<'a, Output, I> for RuleRef<P>
+where
+    I: Input,
+    P: Parser<'a, Output, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
+        self.parser.parse(input)
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::Rule(Rule { name: self.name, definition: Definition::Lazy(self.definition) }))
+    }
+}
+
+/// 没有描述的解析器在描述中显示为名为`label`的终结符，见`labelled`
+pub(crate) struct Labelled<P> {
+    parser: P,
+    label: &'static str,
+}
+
+/// `grammar!`用它包装嵌入的表达式，表达式的源码就是终结符的名称
+pub(crate) fn labelled<P>(parser: P, label: &'static str) -> Labelled<P> {
+    Labelled { parser, label }
+}
+
+impl<'a, P, Output, I> Parser<'a, Output, I> for Labelled<P>
+where
+    I: Input,
+    P: Parser<'a, Output, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
+        self.parser.parse(input)
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax().or_else(|| Some(Syntax::terminal(self.label)))
+    }
+}
+
+/// 携带语法描述的解析器，见`Parser::describe`
+pub(crate) struct Described<P> {
+    parser: P,
+    syntax: fn() -> Syntax,
+}
+
+impl<P> Described<P> {
+    pub(crate) fn new(parser: P, syntax: fn() -> Syntax) -> Self {
+        Self { parser, syntax }
+    }
+}
+
+impl<'a, P, Output, I> Parser<'a, Output, I> for Described<P>
+where
+    I: Input,
+    P: Parser<'a, Output, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, Output, I> {
+        self.parser.parse(input)
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        Some((self.syntax)())
+    }
+}
+
+/// 从一个解析器出发能够到达的所有规则，按照第一次被引用的顺序排列
+/// 没有描述的规则不会出现在其中，它们在别处被当作终结符
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Grammar {
+    rules: Vec<(&'static str, Syntax)>,
+}
+
+impl Grammar {
+    pub(crate) fn new<'a, P, A>(name: &'static str, root: &P) -> Self
+    where
+        P: Parser<'a, A>,
+    {
+        let mut rules = Vec::new();
+        let mut seen = vec![name];
+        let root = match root.syntax() {
+            Some(Syntax::Rule(rule)) if rule.name == name => rule.definition(),
+            syntax => syntax,
+        };
+        let mut pending = root.map(|syntax| (name, syntax)).into_iter().collect::<Vec<_>>();
+
+        // 广度优先，规则的顺序与阅读语法的顺序一致
+        while !pending.is_empty() {
+            for (name, syntax) in std::mem::take(&mut pending) {
+                syntax.for_each_rule(&mut |rule| {
+                    if !seen.contains(&rule.name) {
+                        seen.push(rule.name);
+                        if let Some(definition) = rule.definition() {
+                            pending.push((rule.name, definition));
+                        }
+                    }
+                });
+                rules.push((name, syntax));
+            }
+        }
+        Self { rules }
+    }
+
+    /// 每条规则一张铁路图，纵向排列在同一个SVG中
+    pub(crate) fn to_svg(&self) -> String {
+        let mut body = String::new();
+        let mut width = 0;
+        let mut y = MARGIN;
+        for (name, syntax) in &self.rules {
+            let size = Size::of(syntax);
+            writeln!(body, r#"<text class="name" x="{MARGIN}" y="{}">{name}</text>"#, y + 14)
+                .unwrap();
+            let line = y + NAME_HEIGHT + size.up;
+            let x = MARGIN + CAP;
+            // 开始与结束的标记
+            writeln!(
+                body,
+                r#"<path d="M{} {} v20 m0 -10 h{CAP} M{} {line} h{CAP} m0 -10 v20"/>"#,
+                MARGIN,
+                line - 10,
+                x + size.width,
+            )
+            .unwrap();
+            draw(syntax, x, line, &mut body);
+            width = width.max(x + size.width + CAP + MARGIN);
+            y = line + size.down + MARGIN;
+        }
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{y}\" \
+             viewBox=\"0 0 {width} {y}\">\n{STYLE}\n{body}</svg>\n"
+        )
+    }
+}
+
+/// 每条规则一行：`name ::= 表达式`
+impl fmt::Display for Grammar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.rules.iter().try_for_each(|(name, syntax)| writeln!(f, "{} ::= {}", name, syntax))
+    }
+}
+
+/// 运算的优先级，数字越大结合得越紧
+fn precedence(syntax: &Syntax) -> u8 {
+    match syntax {
+        Syntax::Choice(_) => 0,
+        Syntax::Sequence(_) => 1,
+        _ => 2,
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 优先级低于`min`的子表达式需要加括号
+        fn write_child(f: &mut fmt::Formatter<'_>, syntax: &Syntax, min: u8) -> fmt::Result {
+            if precedence(syntax) < min {
+                write!(f, "({})", syntax)
+            } else {
+                write!(f, "{}", syntax)
+            }
+        }
+
+        fn write_list(
+            f: &mut fmt::Formatter<'_>,
+            items: &[Syntax],
+            sep: &str,
+            min: u8,
+        ) -> fmt::Result {
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(sep)?;
+                }
+                write_child(f, item, min)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Syntax::Literal(text) if text.contains('"') => write!(f, "'{}'", text),
+            Syntax::Literal(text) => write!(f, "\"{}\"", text),
+            Syntax::Terminal(name) => f.write_str(name),
+            Syntax::Rule(rule) => f.write_str(rule.name),
+            Syntax::Sequence(items) => write_list(f, items, " ", 2),
+            Syntax::Choice(alternatives) => write_list(f, alternatives, " | ", 1),
+            Syntax::Optional(inner) => write_child(f, inner, 2).and_then(|_| f.write_str("?")),
+            Syntax::ZeroOrMore(inner) => write_child(f, inner, 2).and_then(|_| f.write_str("*")),
+            Syntax::OneOrMore(inner) => write_child(f, inner, 2).and_then(|_| f.write_str("+")),
+        }
+    }
+}
+
+const STYLE: &str = "<style>\
+path { fill: none; stroke: #333; stroke-width: 1.5; }\
+rect { fill: #f4f4f4; stroke: #333; stroke-width: 1.5; }\
+rect.rule { fill: #dde8f7; }\
+text { font: 13px monospace; text-anchor: middle; }\
+text.name { font-weight: bold; text-anchor: start; }\
+</style>";
+
+/// 四周的留白、规则名所占的高度以及开始和结束标记的宽度
+const MARGIN: usize = 10;
+const NAME_HEIGHT: usize = 24;
+const CAP: usize = 20;
+/// 方框的尺寸，方框的宽度由文本的字符数决定
+const CHAR_WIDTH: usize = 8;
+const PADDING: usize = 10;
+const BOX_HALF_HEIGHT: usize = 11;
+/// 序列中相邻两项的间距、分支两侧的轨道宽度以及分支之间的间距
+const GAP: usize = 10;
+const RAIL: usize = 20;
+const BRANCH_GAP: usize = 10;
+
+/// 图的宽度以及主线上方和下方的高度
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Size {
+    width: usize,
+    up: usize,
+    down: usize,
+}
+
+impl Size {
+    fn of(syntax: &Syntax) -> Self {
+        match syntax {
+            Syntax::Literal(text) | Syntax::Terminal(text) => Self::of_box(text),
+            Syntax::Rule(rule) => Self::of_box(rule.name),
+            Syntax::Sequence(items) => {
+                items.iter().map(Self::of).fold(Size { width: 0, up: 0, down: 0 }, |size, item| {
+                    Size {
+                        // 宽度为0的项（例如空的分支）不占位置，也不需要间距
+                        width: size.width
+                            + item.width
+                            + if size.width > 0 && item.width > 0 { GAP } else { 0 },
+                        up: size.up.max(item.up),
+                        down: size.down.max(item.down),
+                    }
+                })
+            }
+            // 没有候选项的分支什么也不画，与空的序列一样
+            Syntax::Choice(alternatives) if alternatives.is_empty() => {
+                Size { width: 0, up: 0, down: 0 }
+            }
+            Syntax::Choice(alternatives) => {
+                let sizes = alternatives.iter().map(Self::of).collect::<Vec<_>>();
+                let width = sizes.iter().map(|size| size.width).max().unwrap_or(0);
+                let down = sizes[0].down
+                    + sizes[1..].iter().map(|size| BRANCH_GAP + size.up + size.down).sum::<usize>();
+                Size { width: width + 2 * RAIL, up: sizes[0].up, down }
+            }
+            // 跳过的分支在主线上，内容在下方
+            Syntax::Optional(inner) | Syntax::ZeroOrMore(inner) => {
+                let inner = Self::of(inner);
+                let extra = if matches!(syntax, Syntax::ZeroOrMore(_)) { BRANCH_GAP } else { 0 };
+                Size {
+                    width: inner.width + 2 * RAIL,
+                    up: 0,
+                    down: BRANCH_GAP + inner.up + inner.down + extra,
+                }
+            }
+            // 内容在主线上，返回的线在下方
+            Syntax::OneOrMore(inner) => {
+                let inner = Self::of(inner);
+                Size { width: inner.width + 2 * RAIL, up: inner.up, down: inner.down + BRANCH_GAP }
+            }
+        }
+    }
+
+    fn of_box(text: &str) -> Self {
+        Size {
+            width: text.chars().count() * CHAR_WIDTH + 2 * PADDING,
+            up: BOX_HALF_HEIGHT,
+            down: BOX_HALF_HEIGHT,
+        }
+    }
+}
+
+/// 从`(x, y)`进入，从`(x + 宽度, y)`离开
+fn draw(syntax: &Syntax, x: usize, y: usize, out: &mut String) {
+    let size = Size::of(syntax);
+    match syntax {
+        Syntax::Literal(text) => draw_box(text, "literal", x, y, out),
+        Syntax::Terminal(name) => draw_box(name, "terminal", x, y, out),
+        Syntax::Rule(rule) => draw_box(rule.name, "rule", x, y, out),
+        Syntax::Sequence(items) => {
+            let start = x;
+            let mut x = x;
+            for item in items.iter().filter(|item| Size::of(item).width > 0) {
+                if x > start {
+                    writeln!(out, r#"<path d="M{x} {y} h{GAP}"/>"#).unwrap();
+                    x += GAP;
+                }
+                draw(item, x, y, out);
+                x += Size::of(item).width;
+            }
+        }
+        Syntax::Choice(alternatives) => {
+            let mut branch_y = y;
+            for (index, alternative) in alternatives.iter().enumerate() {
+                let branch = Size::of(alternative);
+                if index > 0 {
+                    branch_y += BRANCH_GAP + branch.up;
+                }
+                draw_branch(alternative, x, y, branch_y, size.width, out);
+                branch_y += branch.down;
+            }
+        }
+        Syntax::Optional(inner) | Syntax::ZeroOrMore(inner) => {
+            let inner_size = Size::of(inner);
+            writeln!(out, r#"<path d="M{x} {y} h{}"/>"#, size.width).unwrap();
+            let branch_y = y + BRANCH_GAP + inner_size.up;
+            if matches!(syntax, Syntax::ZeroOrMore(_)) {
+                draw_loop(x + RAIL, branch_y, inner_size, out);
+            }
+            draw_branch(inner, x, y, branch_y, size.width, out);
+        }
+        Syntax::OneOrMore(inner) => {
+            let inner_size = Size::of(inner);
+            draw_branch(inner, x, y, y, size.width, out);
+            draw_loop(x + RAIL, y, inner_size, out);
+        }
+    }
+}
+
+/// 从主线的`x`处分出，在`branch_y`上经过`syntax`，再回到主线的`x + width`处
+fn draw_branch(
+    syntax: &Syntax,
+    x: usize,
+    y: usize,
+    branch_y: usize,
+    width: usize,
+    out: &mut String,
+) {
+    let inner = Size::of(syntax).width;
+    let half = RAIL / 2;
+    let (end, exit) = (x + RAIL + inner, x + width - half);
+    writeln!(
+        out,
+        r#"<path d="M{x} {y} h{half} V{branch_y} h{half} M{end} {branch_y} H{exit} V{y} h{half}"/>"#
+    )
+    .unwrap();
+    draw(syntax, x + RAIL, branch_y, out);
+}
+
+/// 在`syntax`下方从右端绕回左端的线
+fn draw_loop(x: usize, y: usize, size: Size, out: &mut String) {
+    let half = RAIL / 2;
+    let bottom = y + size.down + BRANCH_GAP;
+    writeln!(
+        out,
+        r#"<path d="M{} {y} h{half} V{bottom} H{} V{y} h{half}"/>"#,
+        x + size.width,
+        x - half,
+    )
+    .unwrap();
+}
+
+fn draw_box(text: &str, class: &str, x: usize, y: usize, out: &mut String) {
+    let size = Size::of_box(text);
+    let radius = if class == "literal" { BOX_HALF_HEIGHT } else { 0 };
+    writeln!(
+        out,
+        r#"<rect class="{class}" x="{x}" y="{}" width="{}" height="{}" rx="{radius}"/>"#,
+        y - size.up,
+        size.width,
+        size.up + size.down,
+    )
+    .unwrap();
+    writeln!(out, r#"<text x="{}" y="{}">{}</text>"#, x + size.width / 2, y + 4, escape(text))
+        .unwrap();
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{either, element, match_literal, one_or_more, optional, pair};
+
+    #[test]
+    fn composed_syntax() {
+        let parser = pair(
+            match_literal::<&str>("a"),
+            either(
+                one_or_more(match_literal("b")).map(|_| ()),
+                optional(match_literal("c")).map(|_| ()),
+            ),
+        );
+        assert_eq!(r#""a" ("b"+ | "c"?)"#, parser.syntax().unwrap().to_string());
+        // 任何一部分没有描述时，整体也没有描述
+        assert_eq!(None, pair(match_literal("a"), crate::any_char).syntax());
+    }
+
+    #[test]
+    fn empty_alternatives() {
+        let empty = Syntax::sequence([Syntax::literal("a"), Syntax::choice([])]);
+        assert_eq!(Size::of(&Syntax::literal("a")), Size::of(&empty));
+        assert_eq!("\"a\" ()", empty.to_string());
+        let grammar = Grammar { rules: vec![("empty", Syntax::choice([]))] };
+        assert!(grammar.to_svg().ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn xml_ebnf() {
+        assert_eq!(
+            "element ::= S? (single_element | parent_element) S?\n\
+             single_element ::= element_start \"/>\"\n\
+             parent_element ::= open_element element* S? close_element\n\
+             element_start ::= \"<\" Name attributes\n\
+             open_element ::= element_start \">\"\n\
+             close_element ::= \"</\" Name \">\"\n\
+             attributes ::= (S attribute_pair)*\n\
+             attribute_pair ::= Name \"=\" quoted_string\n\
+             quoted_string ::= '\"' [^\"]* '\"'\n",
+            Grammar::new("element", &element()).to_string()
+        );
+    }
+
+    #[test]
+    fn railroad_svg() {
+        let syntax = Syntax::sequence([
+            Syntax::literal("<"),
+            Syntax::choice([Syntax::terminal("a"), Syntax::terminal("bc")]),
+        ]);
+        let size = Size::of(&syntax);
+        // `<`宽28，分支宽36加上两侧的轨道，第二个分支在主线下方32处
+        assert_eq!(Size { width: 28 + GAP + 36 + 2 * RAIL, up: 11, down: 11 + 10 + 22 }, size);
+
+        let grammar = Grammar { rules: vec![("tag", syntax)] };
+        let svg = grammar.to_svg();
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="174" height="98""#)
+        );
+        assert!(svg.contains(r#"<text class="name" x="10" y="24">tag</text>"#));
+        assert!(
+            svg.contains(r#"<rect class="literal" x="30" y="34" width="28" height="22" rx="11"/>"#)
+        );
+        assert!(svg.contains(r#"<text x="44" y="49">&lt;</text>"#));
+        assert!(
+            svg.contains(r#"<rect class="terminal" x="88" y="66" width="36" height="22" rx="0"/>"#)
+        );
+        assert!(svg.ends_with("</svg>\n"));
+
+        let svg = Grammar::new("element", &element()).to_svg();
+        assert_eq!(9, svg.matches(r#"<text class="name""#).count());
+        assert!(svg.contains(r#">&lt;/</text>"#));
+    }
+}
@@ -0,0 +1,453 @@
+//! TOML子集的配置语法
+//! 支持表（`[server.http]`）、点分键（`a.b = 1`）、字符串、整数、布尔值以及数组，
+//! 不支持浮点数、日期、内联表和表数组
+//! 参考：https://toml.io/cn/v1.0.0
+
+use crate::{
+    Parser, Span, any_char, consumed, either, end_of_input, left, match_literal, newline,
+    one_or_more, optional, pair, recognize, right, space0, whitespace_wrap, zero_or_more,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum TomlValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<TomlValue>),
+    /// 使用`Vec`保存键值对，保持与原文一致的顺序
+    Table(Vec<(String, TomlValue)>),
+}
+
+impl TomlValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            TomlValue::String(_) => "string",
+            TomlValue::Integer(_) => "integer",
+            TomlValue::Boolean(_) => "boolean",
+            TomlValue::Array(_) => "array",
+            TomlValue::Table(_) => "table",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TomlErrorKind {
+    /// 语法错误，区间从无法继续解析的位置开始，到该行结束为止
+    Syntax,
+    /// 同一个键或者同一个表被定义了两次
+    DuplicateKey(String),
+    /// 点分键或表头经过了一个不是表的值，例如先定义`a = 1`再定义`a.b = 2`
+    NotATable(String),
+    /// 数组中的元素类型不一致，区间指向第一个类型不同的元素
+    MixedArray { expected: &'static str, found: &'static str },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TomlError {
+    kind: TomlErrorKind,
+    span: Span,
+}
+
+impl TomlError {
+    fn new(kind: TomlErrorKind, source: &str, slice: &str) -> Self {
+        Self { kind, span: Span::of(source, slice) }
+    }
+}
+
+/// 键的名称以及它在源码中的位置
+#[derive(Clone, Debug)]
+struct Key<'a> {
+    name: String,
+    source: &'a str,
+}
+
+/// 尚未校验的值，数组元素保留各自在源码中的位置，用于报告类型不一致的元素
+#[derive(Clone, Debug)]
+enum RawValue<'a> {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<(&'a str, RawValue<'a>)>),
+}
+
+#[derive(Clone, Debug)]
+enum Line<'a> {
+    Table(Vec<Key<'a>>),
+    KeyValue(Vec<Key<'a>>, RawValue<'a>),
+}
+
+/// 解析整个配置文件，得到根表
+pub(crate) fn parse_toml(input: &str) -> Result<TomlValue, TomlError> {
+    let lines = match document().parse(input) {
+        Ok((_, lines)) => lines,
+        Err(rest) => {
+            let line = rest.split('\n').next().unwrap_or(rest);
+            return Err(TomlError::new(TomlErrorKind::Syntax, input, line));
+        }
+    };
+
+    let mut root = Vec::new();
+    let mut current_table: Vec<Key> = vec![];
+    let mut defined_tables: Vec<Vec<String>> = vec![];
+
+    for line in lines {
+        match line {
+            Line::Table(keys) => {
+                let path = keys.iter().map(|key| key.name.clone()).collect::<Vec<_>>();
+                let last = &keys[keys.len() - 1];
+                if defined_tables.contains(&path) {
+                    return Err(TomlError::new(
+                        TomlErrorKind::DuplicateKey(last.name.clone()),
+                        input,
+                        last.source,
+                    ));
+                }
+                table_at(&mut root, &keys, input)?;
+                defined_tables.push(path);
+                current_table = keys;
+            }
+            Line::KeyValue(keys, value) => {
+                let value = check_value(value, input)?;
+                let (last, parents) = keys.split_last().expect("点分键至少包含一个键");
+                let table = table_at(table_at(&mut root, &current_table, input)?, parents, input)?;
+                if table.iter().any(|(name, _)| *name == last.name) {
+                    return Err(TomlError::new(
+                        TomlErrorKind::DuplicateKey(last.name.clone()),
+                        input,
+                        last.source,
+                    ));
+                }
+                table.push((last.name.clone(), value));
+            }
+        }
+    }
+
+    Ok(TomlValue::Table(root))
+}
+
+/// 沿着`keys`找到对应的表，不存在的表会被隐式地创建
+fn table_at<'t>(
+    mut table: &'t mut Vec<(String, TomlValue)>,
+    keys: &[Key],
+    source: &str,
+) -> Result<&'t mut Vec<(String, TomlValue)>, TomlError> {
+    for key in keys {
+        let index = match table.iter().position(|(name, _)| *name == key.name) {
+            Some(index) => index,
+            None => {
+                table.push((key.name.clone(), TomlValue::Table(vec![])));
+                table.len() - 1
+            }
+        };
+        table = match &mut table[index].1 {
+            TomlValue::Table(table) => table,
+            _ => {
+                return Err(TomlError::new(
+                    TomlErrorKind::NotATable(key.name.clone()),
+                    source,
+                    key.source,
+                ));
+            }
+        };
+    }
+    Ok(table)
+}
+
+/// 数组中所有元素的类型必须与第一个元素相同
+fn check_value(value: RawValue, source: &str) -> Result<TomlValue, TomlError> {
+    match value {
+        RawValue::String(value) => Ok(TomlValue::String(value)),
+        RawValue::Integer(value) => Ok(TomlValue::Integer(value)),
+        RawValue::Boolean(value) => Ok(TomlValue::Boolean(value)),
+        RawValue::Array(items) => {
+            let mut values: Vec<TomlValue> = Vec::with_capacity(items.len());
+            for (item_source, item) in items {
+                let value = check_value(item, source)?;
+                if let Some(first) = values.first() {
+                    if first.type_name() != value.type_name() {
+                        return Err(TomlError::new(
+                            TomlErrorKind::MixedArray {
+                                expected: first.type_name(),
+                                found: value.type_name(),
+                            },
+                            source,
+                            item_source,
+                        ));
+                    }
+                }
+                values.push(value);
+            }
+            Ok(TomlValue::Array(values))
+        }
+    }
+}
+
+/// 行内的空白只有空格和制表符，换行符用来分隔键值对
+fn inline_space<'a>() -> impl Parser<'a, Vec<char>> {
+    zero_or_more(any_char.pred(|c| *c == ' ' || *c == '\t'))
+}
+
+fn inline_wrap<'a, P, A>(parser: P) -> impl Parser<'a, A>
+where
+    P: Parser<'a, A>,
+{
+    right(inline_space(), left(parser, inline_space()))
+}
+
+fn comment<'a>() -> impl Parser<'a, ()> {
+    pair(match_literal("#"), zero_or_more(any_char.pred(|c| *c != '\n'))).map(|_| ())
+}
+
+/// 一行的内容：表头、键值对或者空行，后面可以跟着注释
+fn line<'a>() -> impl Parser<'a, Option<Line<'a>>> {
+    right(
+        inline_space(),
+        left(
+            optional(either(table_header(), key_value())),
+            pair(inline_space(), optional(comment())),
+        ),
+    )
+}
+
+/// 最后一行可以没有换行符
+/// 注意不能直接使用`zero_or_more(left(line(), either(newline(), end_of_input())))`，
+/// 在输入末尾它会不消耗任何输入而一直成功下去
+fn document<'a>() -> impl Parser<'a, Vec<Line<'a>>> {
+    pair(zero_or_more(left(line(), newline())), left(line(), end_of_input()))
+        .map(|(lines, last)| lines.into_iter().chain(Some(last)).flatten().collect())
+}
+
+fn table_header<'a>() -> impl Parser<'a, Line<'a>> {
+    right(match_literal("["), left(inline_wrap(dotted_key()), match_literal("]"))).map(Line::Table)
+}
+
+fn key_value<'a>() -> impl Parser<'a, Line<'a>> {
+    pair(dotted_key(), right(inline_wrap(match_literal("=")), value()))
+        .map(|(keys, value)| Line::KeyValue(keys, value))
+}
+
+fn dotted_key<'a>() -> impl Parser<'a, Vec<Key<'a>>> {
+    pair(simple_key(), zero_or_more(right(inline_wrap(match_literal(".")), simple_key()))).map(
+        |(first, mut rest)| {
+            rest.insert(0, first);
+            rest
+        },
+    )
+}
+
+/// 裸键只能包含ASCII字母、数字、`_`和`-`，其他的键需要加引号
+fn simple_key<'a>() -> impl Parser<'a, Key<'a>> {
+    let bare_key =
+        one_or_more(any_char.pred(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+            .map(|chars| chars.into_iter().collect());
+
+    consumed(either(bare_key, either(basic_string(), literal_string())))
+        .map(|(source, name)| Key { name, source })
+}
+
+fn value<'a>() -> impl Parser<'a, RawValue<'a>> {
+    either(
+        either(basic_string(), literal_string()).map(RawValue::String),
+        either(integer(), either(boolean(), array())),
+    )
+}
+
+/// 与`quoted_string`一样由引号包裹，额外支持`\"`、`\\`、`\n`、`\t`、`\r`转义
+fn basic_string<'a>() -> impl Parser<'a, String> {
+    let escape = right(
+        match_literal("\\"),
+        any_char.pred(|c| "\"\\ntr".contains(*c)).map(|c| match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            c => c,
+        }),
+    );
+    let unescaped = any_char.pred(|c| *c != '"' && *c != '\\' && *c != '\n');
+
+    right(match_literal("\""), left(zero_or_more(either(unescaped, escape)), match_literal("\"")))
+        .map(|chars| chars.into_iter().collect())
+}
+
+/// 单引号包裹的字符串，其中的内容原样保留
+fn literal_string<'a>() -> impl Parser<'a, String> {
+    right(
+        match_literal("'"),
+        left(zero_or_more(any_char.pred(|c| *c != '\'' && *c != '\n')), match_literal("'")),
+    )
+    .map(|chars| chars.into_iter().collect())
+}
+
+/// 十进制整数，数字之间允许使用下划线分隔，例如`1_000`，不允许前导零
+fn integer<'a>() -> impl Parser<'a, RawValue<'a>> {
+    let digit = || any_char.pred(char::is_ascii_digit);
+    let unsigned = either(
+        pair(
+            any_char.pred(|c| matches!(c, '1'..='9')),
+            zero_or_more(pair(optional(match_literal("_")), digit())),
+        )
+        .map(|_| ()),
+        match_literal("0"),
+    );
+
+    recognize(pair(optional(any_char.pred(|c| *c == '+' || *c == '-')), unsigned))
+        .map(|number| number.replace('_', "").parse::<i64>())
+        .pred(Result::is_ok)
+        .map(|number| RawValue::Integer(number.unwrap_or_default()))
+}
+
+fn boolean<'a>() -> impl Parser<'a, RawValue<'a>> {
+    either(
+        match_literal("true").map(|_| RawValue::Boolean(true)),
+        match_literal("false").map(|_| RawValue::Boolean(false)),
+    )
+}
+
+/// 数组可以跨越多行，也允许尾随逗号
+fn array<'a>() -> impl Parser<'a, RawValue<'a>> {
+    // 延迟构造`value`，避免`value`与`array`无限地相互调用
+    let item = || whitespace_wrap(consumed(|input| value().parse(input)));
+
+    right(
+        match_literal("["),
+        left(
+            pair(zero_or_more(left(item(), match_literal(","))), optional(item())),
+            pair(space0(), match_literal("]")),
+        ),
+    )
+    .map(|(mut items, last)| {
+        items.extend(last);
+        RawValue::Array(items)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> TomlValue {
+        TomlValue::String(value.to_owned())
+    }
+
+    fn table(entries: Vec<(&str, TomlValue)>) -> TomlValue {
+        TomlValue::Table(entries.into_iter().map(|(key, value)| (key.to_owned(), value)).collect())
+    }
+
+    #[test]
+    fn toml_parser() {
+        let doc = r#"
+            # 服务配置
+            name = "gateway"
+            debug = false
+
+            [server]
+            host = 'localhost'   # 单引号字符串不处理转义
+            port = 8_080
+            tags = [
+                "a\tb",
+                "c",
+            ]
+
+            [server.limits]
+            "max-body" = 1_048_576
+            timeouts.read = 30
+            timeouts.write = -1
+        "#;
+        let parsed_doc = table(vec![
+            ("name", string("gateway")),
+            ("debug", TomlValue::Boolean(false)),
+            (
+                "server",
+                table(vec![
+                    ("host", string("localhost")),
+                    ("port", TomlValue::Integer(8080)),
+                    ("tags", TomlValue::Array(vec![string("a\tb"), string("c")])),
+                    (
+                        "limits",
+                        table(vec![
+                            ("max-body", TomlValue::Integer(1_048_576)),
+                            (
+                                "timeouts",
+                                table(vec![
+                                    ("read", TomlValue::Integer(30)),
+                                    ("write", TomlValue::Integer(-1)),
+                                ]),
+                            ),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]);
+        assert_eq!(Ok(parsed_doc), parse_toml(doc));
+    }
+
+    #[test]
+    fn nested_arrays() {
+        assert_eq!(
+            Ok(table(vec![(
+                "matrix",
+                TomlValue::Array(vec![
+                    TomlValue::Array(vec![TomlValue::Integer(1), TomlValue::Integer(2)]),
+                    TomlValue::Array(vec![]),
+                ])
+            )])),
+            parse_toml("matrix = [[1, 2], [ ]]")
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let doc = "a = 1\nb = 2\na = 3";
+        assert_eq!(
+            Err(TomlError {
+                kind: TomlErrorKind::DuplicateKey("a".to_owned()),
+                span: Span { start: 12, end: 13 }
+            }),
+            parse_toml(doc)
+        );
+
+        let doc = "[t]\nx = 1\n[t]";
+        assert_eq!(
+            Err(TomlError {
+                kind: TomlErrorKind::DuplicateKey("t".to_owned()),
+                span: Span { start: 11, end: 12 }
+            }),
+            parse_toml(doc)
+        );
+
+        let doc = "a = 1\na.b = 2";
+        assert_eq!(
+            Err(TomlError {
+                kind: TomlErrorKind::NotATable("a".to_owned()),
+                span: Span { start: 6, end: 7 }
+            }),
+            parse_toml(doc)
+        );
+    }
+
+    #[test]
+    fn mixed_array() {
+        let doc = "ports = [80, 443, \"8080\"]";
+        assert_eq!(
+            Err(TomlError {
+                kind: TomlErrorKind::MixedArray { expected: "integer", found: "string" },
+                span: Span { start: 18, end: 24 }
+            }),
+            parse_toml(doc)
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        // 区间指向无法继续解析的`.5`
+        let doc = "a = 1\nb = 1.5\nc = 2";
+        assert_eq!(
+            Err(TomlError { kind: TomlErrorKind::Syntax, span: Span { start: 11, end: 13 } }),
+            parse_toml(doc)
+        );
+        // 整数不允许前导零
+        assert!(parse_toml("a = 01").is_err());
+        // 值不能换到下一行
+        assert!(parse_toml("a =\n1").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! 解析过程的跟踪
+//! 用`.named("attribute_pair")`标记的解析器平时只读取一个全局计数器，然后直接调用被包装的解析器，
+//! 只有在`trace`运行期间才会记录每一次尝试的名称、位置和结果，
+//! 所以不需要修改语法代码，就可以看到`element()`为什么拒绝了某个文档
+
+use std::{
+    cell::RefCell,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{Input, ParseResult, Parser, syntax::Syntax};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TraceNode {
+    name: &'static str,
+    /// 开始解析的位置，相对于传给`trace`的输入的字节偏移
+    offset: usize,
+    outcome: Outcome,
+    /// 在这次尝试中被调用的、带有名称的解析器
+    children: Vec<TraceNode>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// 成功，解析到`end`为止
+    Matched { end: usize },
+    /// 失败，`at`为返回的剩余输入的位置
+    Failed { at: usize },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Trace {
+    roots: Vec<TraceNode>,
+}
+
+struct Tracer {
+    /// 输入的起始地址和长度，用来把剩余输入换算为偏移
+    start: usize,
+    len: usize,
+    /// 正在进行的尝试，栈底是收集最外层尝试的虚拟节点
+    stack: Vec<TraceNode>,
+}
+
+impl Tracer {
+    /// 不属于被跟踪的文本的输入（例如`Token`切片）没有偏移
+    fn offset<I: Input>(&self, input: I) -> Option<usize> {
+        let address = input.address()?;
+        (self.start..=self.start + self.len).contains(&address).then(|| address - self.start)
+    }
+}
+
+thread_local! {
+    static TRACER: RefCell<Option<Tracer>> = const { RefCell::new(None) };
+}
+
+/// 所有线程中正在运行的`trace`的数量，为0时`Named`不访问`TRACER`，直接调用被包装的解析器
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// 运行解析器并记录所有带名称的解析器的尝试
+fn trace<'a, P, A>(parser: &P, input: &'a str) -> (ParseResult<'a, A>, Trace)
+where
+    P: Parser<'a, A>,
+{
+    let root =
+        TraceNode { name: "", offset: 0, outcome: Outcome::Matched { end: 0 }, children: vec![] };
+    let tracer = Tracer { start: input.as_ptr() as usize, len: input.len(), stack: vec![root] };
+    let guard = Active::start(tracer);
+
+    let result = parser.parse(input);
+
+    let root = TRACER.with(|tracer_cell| tracer_cell.borrow_mut().as_mut()?.stack.pop());
+    drop(guard);
+    let roots = root.map(|root| root.children).unwrap_or_default();
+    (result, Trace { roots })
+}
+
+/// 正在运行的`trace`，离开作用域时（包括解析器panic时）恢复外层的跟踪和`ACTIVE`
+struct Active {
+    outer: Option<Tracer>,
+}
+
+impl Active {
+    /// 保存外层的跟踪，这样`trace`可以嵌套调用
+    fn start(tracer: Tracer) -> Self {
+        let outer = TRACER.with(|tracer_cell| tracer_cell.replace(Some(tracer)));
+        ACTIVE.fetch_add(1, Ordering::Relaxed);
+        Self { outer }
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        TRACER.with(|tracer_cell| tracer_cell.replace(self.outer.take()));
+    }
+}
+
+/// `Parser::named`返回的解析器，在语法描述中是一条名为`name`的规则
+pub(crate) struct Named<P> {
+    parser: P,
+    name: &'static str,
+}
+
+impl<P> Named<P> {
+    pub(crate) fn new(parser: P, name: &'static str) -> Self {
+        Self { parser, name }
+    }
+}
+
+impl<'a, P, A, I> Parser<'a, A, I> for Named<P>
+where
+    I: Input,
+    P: Parser<'a, A, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, A, I> {
+        let (parser, name) = (&self.parser, self.name);
+        if ACTIVE.load(Ordering::Relaxed) == 0 {
+            return parser.parse(input);
+        }
+        // 调用被包装的解析器时不能持有借用，它内部的解析器同样会访问`TRACER`
+        let offset = TRACER.with(|tracer_cell| {
+            let mut tracer = tracer_cell.borrow_mut();
+            let tracer = tracer.as_mut()?;
+            let offset = tracer.offset(input)?;
+            let outcome = Outcome::Matched { end: offset };
+            tracer.stack.push(TraceNode { name, offset, outcome, children: vec![] });
+            Some(offset)
+        });
+        if offset.is_none() {
+            return parser.parse(input);
+        }
+
+        let result = parser.parse(input);
+
+        TRACER.with(|tracer_cell| {
+            let mut tracer = tracer_cell.borrow_mut();
+            let tracer = tracer.as_mut().unwrap();
+            let mut node = tracer.stack.pop().unwrap();
+            node.outcome = match &result {
+                Ok((next_input, _)) => {
+                    Outcome::Matched { end: tracer.offset(*next_input).unwrap_or(node.offset) }
+                }
+                Err(rest) => Outcome::Failed { at: tracer.offset(*rest).unwrap_or(node.offset) },
+            };
+            tracer.stack.last_mut().unwrap().children.push(node);
+        });
+        result
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        Some(Syntax::rule(self.name, self.parser.syntax()))
+    }
+}
+
+/// 缩进的树形输出，每次尝试一行：
+/// `element @0 matched ..42`或者`close_element @30 failed at 32`
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_node(f: &mut fmt::Formatter<'_>, node: &TraceNode, depth: usize) -> fmt::Result {
+            write!(f, "{}{} @{} ", "  ".repeat(depth), node.name, node.offset)?;
+            match node.outcome {
+                Outcome::Matched { end } => writeln!(f, "matched ..{}", end)?,
+                Outcome::Failed { at } => writeln!(f, "failed at {}", at)?,
+            }
+            node.children.iter().try_for_each(|child| write_node(f, child, depth + 1))
+        }
+
+        self.roots.iter().try_for_each(|node| write_node(f, node, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        attribute_pair, element, match_literal,
+        xml::{Context, Limits},
+    };
+
+    #[test]
+    fn trace_tree() {
+        let (result, trace) =
+            trace(&attribute_pair(&Context::new(Limits::default())), r#"label="Top""#);
+        assert!(result.is_ok());
+        assert_eq!(
+            "attribute_pair @0 matched ..11\n  quoted_string @6 matched ..11\n",
+            trace.to_string()
+        );
+    }
+
+    #[test]
+    fn rejected_document() {
+        let doc = "<top><bottom/></middle>";
+        let (result, trace) = trace(&element(), doc);
+        assert_eq!(Err("</middle>"), result);
+        // `</middle>`不是子元素，`<`之后的`/`不是合法的名称开头，
+        // 它是一个结束标记，但名称与`<top>`不一致，所以`parent_element`在它的位置失败
+        assert_eq!(
+            "element @0 failed at 14\n\
+            \x20 single_element @0 failed at 4\n\
+            \x20   element_start @0 matched ..4\n\
+            \x20     attributes @4 matched ..4\n\
+            \x20 parent_element @0 failed at 14\n\
+            \x20   open_element @0 matched ..5\n\
+            \x20     element_start @0 matched ..4\n\
+            \x20       attributes @4 matched ..4\n\
+            \x20   element @5 matched ..14\n\
+            \x20     single_element @5 matched ..14\n\
+            \x20       element_start @5 matched ..12\n\
+            \x20         attributes @12 matched ..12\n\
+            \x20   element @14 failed at 15\n\
+            \x20     single_element @14 failed at 15\n\
+            \x20       element_start @14 failed at 15\n\
+            \x20     parent_element @14 failed at 15\n\
+            \x20       open_element @14 failed at 15\n\
+            \x20         element_start @14 failed at 15\n\
+            \x20   close_element @14 matched ..23\n",
+            trace.to_string()
+        );
+    }
+
+    #[test]
+    fn disabled_outside_trace() {
+        let parser = match_literal("a").named("a");
+        assert_eq!(Ok(("", ())), parser.parse("a"));
+        TRACER.with(|tracer| assert!(tracer.borrow().is_none()));
+
+        // 嵌套的跟踪互不干扰
+        let (_, outer) = trace(
+            &(move |input| {
+                let (_, inner) = trace(&match_literal("a").named("inner"), input);
+                assert_eq!(1, inner.roots.len());
+                parser.parse(input)
+            }),
+            "a",
+        );
+        assert_eq!(vec!["a"], outer.roots.iter().map(|node| node.name).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panicking_parser() {
+        let parser = match_literal("a").named("a");
+        let (_, outer) = trace(
+            &(|input| {
+                let panicked = std::panic::catch_unwind(|| {
+                    trace(&(|_: &str| -> ParseResult<()> { panic!("parser bug") }), input)
+                });
+                assert!(panicked.is_err());
+                parser.parse(input)
+            }),
+            "a",
+        );
+        // panic之后外层的跟踪仍然有效
+        assert_eq!(vec!["a"], outer.roots.iter().map(|node| node.name).collect::<Vec<_>>());
+        TRACER.with(|tracer| assert!(tracer.borrow().is_none()));
+    }
+}
@@ -0,0 +1,402 @@
+//! 带有资源限制的XML解析
+//! `element`的语法带着一个`Context`，解析时限制嵌套深度、属性数量、名称与属性值的长度以及输入的总长度，
+//! 同时检查良构性（well-formedness）规则：属性名在同一个元素内不能重复，名称必须符合XML规范
+//! `element()`使用默认的`Limits`，十万层嵌套的`<a>`也不会耗尽栈空间
+//! `parse_xml`在此之上要求文档只有一个根元素，并报告错误的种类
+
+use std::{cell::Cell, fmt, rc::Rc};
+
+use crate::{
+    Element, ParseResult, Parser, Text, element_with, identifier, match_literal, right,
+    syntax::Syntax,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Limits {
+    /// 元素的最大嵌套深度，根元素的深度为1
+    max_depth: usize,
+    /// 单个元素的最大属性数量
+    max_attributes: usize,
+    /// 元素名和属性名的最大字节数
+    max_name_len: usize,
+    /// 属性值的最大字节数
+    max_value_len: usize,
+    /// 整个输入的最大字节数
+    max_input_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_attributes: 256,
+            max_name_len: 256,
+            max_value_len: 64 * 1024,
+            max_input_len: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// 在默认值的基础上修改，例如`Limits::default().max_depth(32)`
+impl Limits {
+    pub(crate) fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub(crate) fn max_attributes(mut self, max_attributes: usize) -> Self {
+        self.max_attributes = max_attributes;
+        self
+    }
+
+    pub(crate) fn max_name_len(mut self, max_name_len: usize) -> Self {
+        self.max_name_len = max_name_len;
+        self
+    }
+
+    pub(crate) fn max_value_len(mut self, max_value_len: usize) -> Self {
+        self.max_value_len = max_value_len;
+        self
+    }
+
+    pub(crate) fn max_input_len(mut self, max_input_len: usize) -> Self {
+        self.max_input_len = max_input_len;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum XmlErrorKind {
+    Syntax,
+    InputTooLarge,
+    TooDeep,
+    TooManyAttributes,
+    NameTooLong,
+    ValueTooLong,
+    /// 同一个元素中出现了重名的属性，位置指向第二次出现的属性名
+    DuplicateAttribute,
+    /// 名称中包含XML规范不允许的字符，例如`<1st/>`或`<a b$c="1"/>`
+    InvalidName,
+    /// 根元素之后还有其他元素
+    MultipleRoots,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct XmlError {
+    pub(crate) kind: XmlErrorKind,
+    /// 出错位置在输入中的字节偏移
+    pub(crate) offset: usize,
+}
+
+impl fmt::Display for XmlErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            XmlErrorKind::Syntax => "syntax error",
+            XmlErrorKind::InputTooLarge => "input is too large",
+            XmlErrorKind::TooDeep => "elements are nested too deeply",
+            XmlErrorKind::TooManyAttributes => "too many attributes",
+            XmlErrorKind::NameTooLong => "name is too long",
+            XmlErrorKind::ValueTooLong => "attribute value is too long",
+            XmlErrorKind::DuplicateAttribute => "duplicate attribute",
+            XmlErrorKind::InvalidName => "invalid name",
+            XmlErrorKind::MultipleRoots => "more than one root element",
+        })
+    }
+}
+
+/// 解析过程中共享的状态，同一个`element_with`构造的整个语法或者同一个`streaming::Driver`共用一个
+/// 超出限制是致命错误，如果只是让解析器返回`Err`，它会被`either`和`zero_or_more`的回溯吞掉，
+/// 所以需要把第一个致命错误记录下来，之后所有的解析器都直接失败
+pub(crate) struct Context {
+    limits: Limits,
+    /// 错误类型以及出错处在内存中的地址，`pred`之类的检查只能拿到解析结果中的切片，
+    /// 切片的地址与剩余输入的地址一样可以换算为偏移
+    error: Cell<Option<(XmlErrorKind, usize)>>,
+    /// 正在解析的元素的嵌套深度
+    depth: Cell<usize>,
+}
+
+impl Context {
+    pub(crate) fn new(limits: Limits) -> Rc<Self> {
+        Rc::new(Self { limits, error: Cell::new(None), depth: Cell::new(0) })
+    }
+
+    /// 记录第一个致命错误，`at`从出错的位置开始，可以是剩余输入，也可以是解析结果中的切片
+    /// 总是返回`false`，可以直接作为`pred`的结果
+    pub(crate) fn fail(&self, kind: XmlErrorKind, at: &str) -> bool {
+        if self.error.get().is_none() {
+            self.error.set(Some((kind, at.as_ptr() as usize)));
+        }
+        false
+    }
+
+    pub(crate) fn failed(&self) -> bool {
+        self.error.get().is_some()
+    }
+
+    pub(crate) fn error_kind(&self) -> Option<XmlErrorKind> {
+        self.error.get().map(|(kind, _)| kind)
+    }
+
+    /// 第一个致命错误的种类以及出错处在内存中的地址
+    pub(crate) fn error(&self) -> Option<(XmlErrorKind, usize)> {
+        self.error.get()
+    }
+
+    /// 开始新的一次解析
+    pub(crate) fn reset(&self) {
+        self.error.set(None);
+        self.depth.set(0);
+    }
+
+    /// `depth`是新的元素所在的深度，根元素为1
+    pub(crate) fn check_depth(&self, depth: usize, at: &str) -> bool {
+        depth <= self.limits.max_depth || self.fail(XmlErrorKind::TooDeep, at)
+    }
+
+    pub(crate) fn check_name(&self, name: &str, at: &str) -> bool {
+        name.len() <= self.limits.max_name_len || self.fail(XmlErrorKind::NameTooLong, at)
+    }
+
+    pub(crate) fn check_value(&self, value: &str, at: &str) -> bool {
+        value.len() <= self.limits.max_value_len || self.fail(XmlErrorKind::ValueTooLong, at)
+    }
+
+    pub(crate) fn check_attributes(&self, count: usize, at: &str) -> bool {
+        count <= self.limits.max_attributes || self.fail(XmlErrorKind::TooManyAttributes, at)
+    }
+}
+
+/// 限制`parser`的嵌套深度，见`nested`
+pub(crate) struct Nested<P> {
+    ctx: Rc<Context>,
+    starts: fn(&str) -> bool,
+    parser: P,
+}
+
+/// 每进入一层`parser`深度加一，超出`max_depth`时在递归之前失败，保证栈的使用量有上限
+/// 只有输入确实以新的一层开头，也就是`starts`返回`true`时才算超出限制，
+/// 例如最深一层的元素在它的结束标记处尝试解析子元素时只是普通的失败
+/// 已经出现致命错误时直接失败
+pub(crate) fn nested<P>(ctx: &Rc<Context>, starts: fn(&str) -> bool, parser: P) -> Nested<P> {
+    Nested { ctx: ctx.clone(), starts, parser }
+}
+
+impl<'a, P, A> Parser<'a, A> for Nested<P>
+where
+    P: Parser<'a, A>,
+{
+    fn parse(&self, input: &'a str) -> ParseResult<'a, A> {
+        let depth = self.ctx.depth.get() + 1;
+        if self.ctx.failed() {
+            return Err(input);
+        }
+        if depth > self.ctx.limits.max_depth {
+            if (self.starts)(input) {
+                self.ctx.fail(XmlErrorKind::TooDeep, input);
+            }
+            return Err(input);
+        }
+
+        self.ctx.depth.set(depth);
+        let result = self.parser.parse(input);
+        self.ctx.depth.set(depth - 1);
+        result
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
+    }
+}
+
+/// 不再回溯的部分，见`cut`
+pub(crate) struct Cut<P> {
+    ctx: Rc<Context>,
+    parser: P,
+}
+
+/// 前面的输入已经确定了语法结构，例如`</`之后只能是名称和`>`，属性名和`=`之后只能是属性值，
+/// 此时`parser`失败是致命的语法错误，在失败的位置报告，而不是回溯到外层元素的开头
+/// 分块的输入还不完整时不是错误
+pub(crate) fn cut<P>(ctx: &Rc<Context>, parser: P) -> Cut<P> {
+    Cut { ctx: ctx.clone(), parser }
+}
+
+impl<'a, P, A, I> Parser<'a, A, I> for Cut<P>
+where
+    I: Text<'a>,
+    P: Parser<'a, A, I>,
+{
+    fn parse(&self, input: I) -> ParseResult<'a, A, I> {
+        self.parser.parse(input).inspect_err(|rest| {
+            if rest.needed().is_none() {
+                self.ctx.fail(XmlErrorKind::Syntax, rest.as_str());
+            }
+        })
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
+    }
+}
+
+/// 语法的入口，见`limited`
+pub(crate) struct Limited<P> {
+    ctx: Rc<Context>,
+    parser: P,
+}
+
+/// 每次解析之前清除上一次的错误，出现致命错误时在出错的位置失败
+pub(crate) fn limited<P>(ctx: &Rc<Context>, parser: P) -> Limited<P> {
+    Limited { ctx: ctx.clone(), parser }
+}
+
+impl<'a, P, A> Parser<'a, A> for Limited<P>
+where
+    P: Parser<'a, A>,
+{
+    fn parse(&self, input: &'a str) -> ParseResult<'a, A> {
+        self.ctx.reset();
+
+        let max_input_len = self.ctx.limits.max_input_len;
+        let result = if input.len() > max_input_len {
+            // 出错的位置落在多字节字符的中间时，退回到这个字符的开头
+            let offset = (0..=max_input_len).rev().find(|&i| input.is_char_boundary(i));
+            self.ctx.fail(XmlErrorKind::InputTooLarge, &input[offset.unwrap_or(0)..]);
+            Err(input)
+        } else {
+            self.parser.parse(input)
+        };
+
+        match self.ctx.error.get() {
+            Some((_, address)) => Err(&input[address - input.as_ptr() as usize..]),
+            None => result,
+        }
+    }
+
+    fn syntax(&self) -> Option<Syntax> {
+        self.parser.syntax()
+    }
+}
+
+/// 解析完整的XML文档，文档只能包含一个根元素
+pub(crate) fn parse_xml(input: &str, limits: &Limits) -> Result<Element, XmlError> {
+    let ctx = Context::new(limits.clone());
+    let (kind, rest) = match element_with(&ctx).parse(input) {
+        Ok(("", element)) => return Ok(element),
+        Ok((rest, _)) if right(match_literal("<"), identifier).parse(rest).is_ok() => {
+            (XmlErrorKind::MultipleRoots, rest)
+        }
+        Ok((rest, _)) => (XmlErrorKind::Syntax, rest),
+        Err(rest) => (ctx.error_kind().unwrap_or(XmlErrorKind::Syntax), rest),
+    };
+    Err(XmlError { kind, offset: input.len() - rest.len() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element;
+
+    fn error(kind: XmlErrorKind, offset: usize) -> Result<Element, XmlError> {
+        Err(XmlError { kind, offset })
+    }
+
+    #[test]
+    fn same_grammar_as_element() {
+        let doc = r#"
+            <top label="Top">
+                <semi-bottom label="Bottom"/>
+                <middle>
+                    <bottom label="Another bottom"/>
+                </middle>
+            </top>"#;
+        let (_, expected) = element().parse(doc).unwrap();
+        assert_eq!(Ok(expected), parse_xml(doc, &Limits::default()));
+
+        let doc = "<top>\n    <bottom/>\n</middle>";
+        assert_eq!(error(XmlErrorKind::Syntax, 20), parse_xml(doc, &Limits::default()));
+    }
+
+    #[test]
+    fn error_positions() {
+        let limits = Limits::default();
+        let doc = "<a><b><c><d/></c></x></b></a>";
+        assert_eq!(error(XmlErrorKind::Syntax, 17), parse_xml(doc, &limits));
+        // `</`之后缺少`>`
+        assert_eq!(error(XmlErrorKind::Syntax, 13), parse_xml("<a><b><c/></b</a>", &limits));
+        assert_eq!(error(XmlErrorKind::Syntax, 8), parse_xml(r#"<a><b x='1'/></a>"#, &limits));
+        assert_eq!(error(XmlErrorKind::Syntax, 4), parse_xml("<a/>trailing", &limits));
+    }
+
+    #[test]
+    fn deep_nesting_fails_cleanly() {
+        let depth = 100_000;
+        let doc = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        let limits = Limits::default();
+        // 第129个`<a>`超出了限制
+        assert_eq!(error(XmlErrorKind::TooDeep, 128 * 3), parse_xml(&doc, &limits));
+        // `element`使用同样的默认限制
+        assert_eq!(Err(&doc[128 * 3..]), element().parse(&doc));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let limits = Limits::default().max_depth(3);
+        assert!(parse_xml("<a><b><c/></b></a>", &limits).is_ok());
+        // 最深一层的元素在结束标记处尝试子元素不算超出限制
+        assert!(parse_xml("<a><b><c> </c></b></a>", &limits).is_ok());
+        assert_eq!(
+            error(XmlErrorKind::TooDeep, 9),
+            parse_xml("<a><b><c><d/></c></b></a>", &limits)
+        );
+    }
+
+    #[test]
+    fn attribute_limits() {
+        let limits = Limits::default().max_attributes(2).max_name_len(4).max_value_len(3);
+        assert!(parse_xml(r#"<a x="1" y="22"/>"#, &limits).is_ok());
+        assert_eq!(
+            error(XmlErrorKind::TooManyAttributes, 2),
+            parse_xml(r#"<a x="1" y="2" z="3"/>"#, &limits)
+        );
+        assert_eq!(error(XmlErrorKind::NameTooLong, 3), parse_xml(r#"<a label="1"/>"#, &limits));
+        assert_eq!(error(XmlErrorKind::NameTooLong, 1), parse_xml("<abcde/>", &limits));
+        assert_eq!(error(XmlErrorKind::ValueTooLong, 5), parse_xml(r#"<a x="long"/>"#, &limits));
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        let limits = Limits::default();
+        assert_eq!(
+            error(XmlErrorKind::DuplicateAttribute, 20),
+            parse_xml(r#"<top><a a="1" b="2" a="3"/></top>"#, &limits)
+        );
+    }
+
+    #[test]
+    fn names() {
+        let limits = Limits::default();
+        assert!(
+            parse_xml(r#"<ns:item _id="1" data.x="2" xml:lang="zh"><子元素/></ns:item>"#, &limits)
+                .is_ok()
+        );
+        assert_eq!(error(XmlErrorKind::InvalidName, 1), parse_xml("<1st/>", &limits));
+        assert_eq!(error(XmlErrorKind::InvalidName, 3), parse_xml(r#"<a b$c="1"/>"#, &limits));
+        assert_eq!(error(XmlErrorKind::InvalidName, 4), parse_xml("<a><-b/></a>", &limits));
+    }
+
+    #[test]
+    fn single_root() {
+        let limits = Limits::default();
+        assert_eq!(error(XmlErrorKind::MultipleRoots, 5), parse_xml("<a/>\n<b/>", &limits));
+        assert_eq!(error(XmlErrorKind::Syntax, 5), parse_xml("<a/>\n</b>", &limits));
+    }
+
+    #[test]
+    fn input_limit() {
+        let limits = Limits::default().max_input_len(8);
+        assert!(parse_xml("<a></a>", &limits).is_ok());
+        assert_eq!(error(XmlErrorKind::InputTooLarge, 8), parse_xml("<a>  </a>", &limits));
+    }
+}
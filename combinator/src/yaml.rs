@@ -0,0 +1,178 @@
+//! YAML子集
+//! 支持块映射（`key: value`）、块序列（`- item`）以及单行的标量，标量可以使用双引号
+//! 不支持流式写法（`[a, b]`、`{a: b}`）、多行标量、锚点、标签和注释，
+//! 也不支持`- key: value`这种紧凑的写法，序列项中的映射需要写在`-`的下一行
+//! 子块必须比父级缩进更深，所以`key:`下面的序列同样需要缩进
+//! 参考：https://yaml.org/spec/1.2.2/
+
+use crate::{
+    BoxedParser, Parser, any_char, either, end_of_input,
+    indent::{IndentError, aligned_block, blank_lines, indent, indented_block},
+    left, match_literal, newline, one_or_more, pair, quoted_string, recognize, right, zero_or_more,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum YamlValue {
+    Scalar(String),
+    Sequence(Vec<YamlValue>),
+    /// 使用`Vec`保存键值对，保持与原文一致的顺序
+    Mapping(Vec<(String, YamlValue)>),
+}
+
+/// 解析整个文档，顶层的块从第0列开始
+pub(crate) fn parse_yaml(input: &str) -> Result<YamlValue, IndentError> {
+    match left(block(0), end_of_input()).parse(input) {
+        Ok((_, value)) => Ok(value),
+        Err(rest) => Err(IndentError::at(input, rest)),
+    }
+}
+
+/// 从`column`列开始的序列或者映射，由第一行是否以`-`开头决定
+fn block<'a>(column: usize) -> BoxedParser<'a, YamlValue> {
+    let sequence = aligned_block(column, sequence_item(column)).map(YamlValue::Sequence);
+    let mapping = aligned_block(column, mapping_entry(column)).map(YamlValue::Mapping);
+    let marker = right(blank_lines(), right(indent(column), sequence_marker()));
+    BoxedParser::new(move |input| {
+        if marker.parse(input).is_ok() { sequence.parse(input) } else { mapping.parse(input) }
+    })
+}
+
+/// `-`之后是空格或者行尾，`-1`这样的标量不是序列项
+fn sequence_marker<'a>() -> impl Parser<'a, ()> {
+    left(match_literal("-"), either(match_literal(" "), line_end()))
+}
+
+fn sequence_item<'a>(column: usize) -> impl Parser<'a, YamlValue> {
+    right(match_literal("-"), value(column))
+}
+
+fn mapping_entry<'a>(column: usize) -> impl Parser<'a, (String, YamlValue)> {
+    pair(key(), right(match_literal(":"), value(column)))
+}
+
+/// 同一行的标量，或者换行之后缩进更深的块
+fn value<'a>(column: usize) -> impl Parser<'a, YamlValue> {
+    let inline = right(one_or_more(match_literal(" ")), left(scalar(), line_end()));
+    // 失败时`either`返回第二个解析器的错误，子块中的错误位置更有用，所以放在后面
+    either(inline.map(YamlValue::Scalar), right(line_end(), indented_block(column, block)))
+}
+
+fn key<'a>() -> impl Parser<'a, String> {
+    let plain =
+        recognize(one_or_more(any_char.pred(|c| c.is_alphanumeric() || *c == '_' || *c == '-')));
+    either(quoted_string(), plain.map(str::to_owned))
+}
+
+/// 普通的标量到行尾为止，去掉末尾的空格，不能包含`: `，否则就是被写在一行里的映射
+fn scalar<'a>() -> impl Parser<'a, String> {
+    let plain = recognize(one_or_more(any_char.pred(|c| *c != '\n' && *c != '\r')))
+        .map(str::trim_end)
+        .pred(|text| !text.contains(": ") && !text.ends_with(':'))
+        .map(str::to_owned);
+    either(quoted_string(), plain)
+}
+
+/// 行尾的空格以及换行符，最后一行可以没有换行符
+fn line_end<'a>() -> impl Parser<'a, ()> {
+    right(zero_or_more(match_literal(" ")), either(newline(), end_of_input()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Span,
+        indent::IndentErrorKind::{InconsistentDedent, Syntax, UnexpectedIndent},
+    };
+
+    fn scalar(value: &str) -> YamlValue {
+        YamlValue::Scalar(value.to_owned())
+    }
+
+    fn mapping(entries: Vec<(&str, YamlValue)>) -> YamlValue {
+        YamlValue::Mapping(
+            entries.into_iter().map(|(key, value)| (key.to_owned(), value)).collect(),
+        )
+    }
+
+    #[test]
+    fn yaml_parser() {
+        let doc = "\
+name: combinator
+version: \"0.1.0\"
+tags:
+  - parser
+  - -1
+
+server:
+    host: localhost
+    ports:
+      - 80
+      - 443
+    routes:
+      -
+        path: /
+        methods:
+          - GET
+      -
+        - nested
+";
+        let parsed_doc = mapping(vec![
+            ("name", scalar("combinator")),
+            ("version", scalar("0.1.0")),
+            ("tags", YamlValue::Sequence(vec![scalar("parser"), scalar("-1")])),
+            (
+                "server",
+                mapping(vec![
+                    ("host", scalar("localhost")),
+                    ("ports", YamlValue::Sequence(vec![scalar("80"), scalar("443")])),
+                    (
+                        "routes",
+                        YamlValue::Sequence(vec![
+                            mapping(vec![
+                                ("path", scalar("/")),
+                                ("methods", YamlValue::Sequence(vec![scalar("GET")])),
+                            ]),
+                            YamlValue::Sequence(vec![scalar("nested")]),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]);
+        assert_eq!(Ok(parsed_doc), parse_yaml(doc));
+        assert_eq!(
+            Ok(YamlValue::Sequence(vec![scalar("a"), scalar("b")])),
+            parse_yaml("- a  \r\n- b")
+        );
+    }
+
+    #[test]
+    fn yaml_errors() {
+        let doc = "server:\n    host: a\n  port: 1\n";
+        assert_eq!(
+            Err(IndentError {
+                kind: InconsistentDedent { found: 2, expected: vec![0, 4] },
+                span: Span { start: 20, end: 29 },
+            }),
+            parse_yaml(doc)
+        );
+
+        let doc = "name: a\n  extra: b\n";
+        assert_eq!(
+            Err(IndentError {
+                kind: UnexpectedIndent { found: 2 },
+                span: Span { start: 8, end: 18 }
+            }),
+            parse_yaml(doc)
+        );
+
+        // 序列与映射不能混在同一个块中
+        let doc = "- a\nb: c\n";
+        assert_eq!(
+            Err(IndentError { kind: Syntax, span: Span { start: 4, end: 8 } }),
+            parse_yaml(doc)
+        );
+        // 紧凑的写法被当作写在一行里的映射拒绝
+        assert!(parse_yaml("- a: b\n").is_err());
+    }
+}
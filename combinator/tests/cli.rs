@@ -0,0 +1,173 @@
+//! `xml`命令行工具的集成测试，输入文件位于`fixtures/xml`
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+fn fixture(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/xml")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn xml(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_xml"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap()
+}
+
+#[test]
+fn validate() {
+    let output = xml(&["validate", &fixture("valid.xml")], "");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("", stderr(&output));
+
+    let path = fixture("mismatched.xml");
+    let output = xml(&["validate", &path], "");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(format!("{}:3:1: error: syntax error\n</middle>\n^\n", path), stderr(&output));
+
+    // 列号按字符计算，`^`之前保留制表符
+    let path = fixture("duplicate-attribute.xml");
+    let output = xml(&["validate", &path], "");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        format!(
+            "{}:2:13: error: duplicate attribute\n\t<子元素 a=\"1\" a=\"2\"/>\n\t{}^\n",
+            path,
+            " ".repeat(11)
+        ),
+        stderr(&output)
+    );
+}
+
+#[test]
+fn error_positions() {
+    // 嵌套在多层元素中的错误报告在出错的结束标记处，而不是外层元素的开头
+    let output = xml(&["validate"], "<top>\n  <middle>\n    <bottom/>\n  </midle>\n</top>");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("<stdin>:4:3: error: syntax error\n  </midle>\n  ^\n", stderr(&output));
+
+    for (doc, expected) in [
+        // 不支持文本内容
+        ("<a>hi</a>trailing", "<stdin>:1:4: error: syntax error\n<a>hi</a>trailing\n   ^\n"),
+        // 根元素之后的多余内容
+        ("<a></a>trailing", "<stdin>:1:8: error: syntax error\n<a></a>trailing\n       ^\n"),
+        // 属性名和`=`之后必须是带引号的值
+        ("<a><b x=1/></a>", "<stdin>:1:9: error: syntax error\n<a><b x=1/></a>\n        ^\n"),
+    ] {
+        let output = xml(&["validate"], doc);
+        assert_eq!(Some(1), output.status.code());
+        assert_eq!(expected, stderr(&output), "{}", doc);
+    }
+}
+
+#[test]
+fn max_depth() {
+    let doc = "<a><b><c/></b></a>";
+    let output = xml(&["validate", "--max-depth", "3"], doc);
+    assert_eq!(Some(0), output.status.code());
+
+    let output = xml(&["validate", "--max-depth", "2", "-"], doc);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "<stdin>:1:7: error: elements are nested too deeply\n<a><b><c/></b></a>\n      ^\n",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn format() {
+    let output =
+        xml(&["format"], r#"<top label="Top"><semi-bottom/><middle><bottom/></middle></top>"#);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        "<top label=\"Top\">\n  <semi-bottom/>\n  <middle>\n    <bottom/>\n  </middle>\n</top>\n",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn json() {
+    let output = xml(&["json", &fixture("valid.xml")], "");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        concat!(
+            r#"{"top":{"@label":"Top","semi-bottom":[{"@label":"Bottom"}],"#,
+            r#""middle":[{"bottom":[{"@label":"Another bottom"}]}]}}"#,
+            "\n"
+        ),
+        stdout(&output)
+    );
+
+    let output = xml(&["json", "--ordered", "-"], "<a><b/><c/><b/></a>");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("{\"a\":{\"#children\":[{\"b\":{}},{\"c\":{}},{\"b\":{}}]}}\n", stdout(&output));
+}
+
+#[test]
+fn query() {
+    let output = xml(&["query", "//*[@label]", &fixture("valid.xml")], "");
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        concat!(
+            "<top label=\"Top\"><semi-bottom label=\"Bottom\"/><middle>",
+            "<bottom label=\"Another bottom\"/></middle></top>\n",
+            "<semi-bottom label=\"Bottom\"/>\n",
+            "<bottom label=\"Another bottom\"/>\n",
+        ),
+        stdout(&output)
+    );
+
+    // 没有结果时与`grep`一样返回1
+    let output = xml(&["query", "top/missing", &fixture("valid.xml")], "");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("", stdout(&output));
+
+    let output = xml(&["query", "top[", &fixture("valid.xml")], "");
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!("error: invalid selector at `[`\n", stderr(&output));
+}
+
+#[test]
+fn usage_errors() {
+    let output = xml(&[], "");
+    assert_eq!(Some(2), output.status.code());
+    assert!(stderr(&output).starts_with("error: missing command\n\nusage: xml"));
+
+    for (args, message) in [
+        (&["convert"][..], "error: unknown command `convert`"),
+        (&["query"][..], "error: missing selector"),
+        (&["format", "--ordered"][..], "error: unknown option `--ordered`"),
+        (&["validate", "a.xml", "b.xml"][..], "error: unexpected argument `b.xml`"),
+        (&["validate", "--max-depth"][..], "error: `--max-depth` expects a positive number"),
+        (&["validate", "--max-depth", "0"][..], "error: `--max-depth` expects a positive number"),
+    ] {
+        let output = xml(args, "");
+        assert_eq!(Some(2), output.status.code());
+        assert!(stderr(&output).starts_with(message), "{:?}", args);
+    }
+
+    let path = fixture("missing.xml");
+    let output = xml(&["validate", &path], "");
+    assert_eq!(Some(2), output.status.code());
+    assert!(stderr(&output).starts_with(&format!("{}: ", path)));
+}